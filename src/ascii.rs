@@ -0,0 +1,253 @@
+use std::fmt::Write;
+
+use crate::{diagram::*, field::*, lagrangian::*, layout::*};
+
+const COLUMNS: usize = 48;
+const ROWS: usize = 15;
+const MARGIN: usize = 6;
+/// Room around the picture for self-loops of the outermost vertices, trimmed again when rendering
+const PADDING: (usize, usize) = (10, 5);
+/// Terminal cells are about twice as tall as they are wide; points are laid out in half rows.
+const ASPECT: f64 = 2.0;
+
+/// Characters for horizontal, vertical, rising and falling stretches of a line; massive lines use
+/// the heavy variants where there are any.
+fn strokes(kind: FieldKind, massive: bool) -> [&'static str; 4] {
+    match (kind, massive) {
+        (RealScalar | ComplexScalar(_), false) => ["╌", "╎", "╱", "╲"],
+        (RealScalar | ComplexScalar(_), true) => ["╍", "╏", "╱", "╲"],
+        (RealVector | ComplexVector(_), _) => ["∿", "≀", "⟋", "⟍"],
+        (Spinor(_), false) => ["─", "│", "╱", "╲"],
+        (Spinor(_), true) => ["━", "┃", "╱", "╲"],
+    }
+}
+
+struct Canvas {
+    cells: Vec<Vec<String>>,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        Canvas {
+            cells: vec![vec![String::from(" "); COLUMNS + 2 * PADDING.0]; ROWS + 2 * PADDING.1],
+        }
+    }
+
+    fn cell(p: Point) -> Option<(usize, usize)> {
+        let (x, y) = (p.0.round(), (p.1 / ASPECT).round());
+        let (x, y) = (x + PADDING.0 as f64, y + PADDING.1 as f64);
+        if x < 0.0 || y < 0.0 || x >= (COLUMNS + 2 * PADDING.0) as f64 {
+            return None;
+        }
+        if y >= (ROWS + 2 * PADDING.1) as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    fn put(&mut self, p: Point, text: &str) {
+        if let Some((x, y)) = Canvas::cell(p) {
+            self.cells[y][x] = text.to_string();
+        }
+    }
+
+    /// Writes `text` starting at `p`, or ending at `p` if `right` is false.
+    fn text(&mut self, p: Point, text: &str, right: bool) {
+        let Some((x, y)) = Canvas::cell(p) else {
+            return;
+        };
+        let glyphs = glyphs(text);
+        let start = if right {
+            x
+        } else {
+            (x + 1).saturating_sub(glyphs.len())
+        };
+        for (k, g) in glyphs.into_iter().enumerate() {
+            if let Some(c) = self.cells[y].get_mut(start + k) {
+                *c = g;
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let rows: Vec<_> = self
+            .cells
+            .iter()
+            .map(|x| x.concat().trim_end().to_string())
+            .collect();
+        let first = rows.iter().position(|x| !x.is_empty()).unwrap_or(0);
+        let last = rows.iter().rposition(|x| !x.is_empty()).unwrap_or(0);
+        let indent = rows
+            .iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| x.len() - x.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        let mut res = String::new();
+        for x in &rows[first..=last] {
+            writeln!(res, "{}", x.get(indent..).unwrap_or("")).unwrap();
+        }
+        res
+    }
+}
+
+/// Splits text into characters, keeping combining marks with the character before them.
+fn glyphs(text: &str) -> Vec<String> {
+    let mut res: Vec<String> = vec![];
+    for c in text.chars() {
+        match res.last_mut() {
+            Some(last) if ('\u{300}'..='\u{36f}').contains(&c) => last.push(c),
+            _ => res.push(c.to_string()),
+        }
+    }
+    res
+}
+
+fn stroke(strokes: [&'static str; 4], dx: f64, dy: f64) -> &'static str {
+    let [horizontal, vertical, rising, falling] = strokes;
+    let angle = dy.atan2(dx).to_degrees().rem_euclid(180.0);
+    match angle {
+        a if !(22.5..157.5).contains(&a) => horizontal,
+        a if (67.5..112.5).contains(&a) => vertical,
+        a if a < 90.0 => falling,
+        _ => rising,
+    }
+}
+
+fn arrow(dx: f64, dy: f64) -> &'static str {
+    if dx.abs() >= dy.abs() / ASPECT {
+        if dx > 0.0 {
+            "▶"
+        } else {
+            "◀"
+        }
+    } else if dy > 0.0 {
+        "▼"
+    } else {
+        "▲"
+    }
+}
+
+pub fn diagram(d: &Diagram, lagrangian: &CheckedLagrangian) -> String {
+    let Layout { positions, edges } = layout(d);
+    let place = |p: Point| {
+        (
+            MARGIN as f64 + p.0 * (COLUMNS - 2 * MARGIN - 1) as f64,
+            ASPECT * (1.0 + p.1 * (ROWS - 3) as f64),
+        )
+    };
+    let positions: Vec<_> = positions.into_iter().map(place).collect();
+
+    let mut canvas = Canvas::new();
+    for Edge { line, shape } in &edges {
+        let kind = line.field.kind();
+        let strokes = strokes(kind, lagrangian.mass(&line.field).is_some());
+        if let Shape::Loop(dir) = *shape {
+            snail(&mut canvas, line, strokes, positions[line.from], dir);
+            continue;
+        }
+        let (a, b) = (positions[line.from], positions[line.to]);
+        let points = sample(*shape, a, b, 0.0, 64);
+        let (mut last, mut count) = (None, 0);
+        for w in points.windows(2) {
+            let cell = Canvas::cell(w[0]);
+            if cell == last {
+                continue;
+            }
+            last = cell;
+            count += 1;
+            let s = stroke(strokes, w[1].0 - w[0].0, w[1].1 - w[0].1);
+            // diagonal scalars would look like fermions without the gaps
+            let diagonal = s == strokes[2] || s == strokes[3];
+            if matches!(kind, RealScalar | ComplexScalar(_)) && diagonal && count % 2 == 0 {
+                continue;
+            }
+            canvas.put(w[0], s);
+        }
+        if let Some(forward) = line.arrow() {
+            let mid = points.len() / 2;
+            let (p, q) = (points[mid - 1], points[mid + 1]);
+            let (dx, dy) = if forward {
+                (q.0 - p.0, q.1 - p.1)
+            } else {
+                (p.0 - q.0, p.1 - q.1)
+            };
+            canvas.put(points[mid], arrow(dx, dy));
+        }
+    }
+
+    let middle = COLUMNS as f64 / 2.0;
+    for (u, &p) in d.nodes().iter().zip(&positions) {
+        if let Some(field) = u.particle() {
+            let right = p.0 >= middle;
+            let offset = if right { 2.0 } else { -2.0 };
+            canvas.put(p, "•");
+            canvas.text((p.0 + offset, p.1), &label(&field), right);
+        } else {
+            let interaction = u.interaction().unwrap();
+            let k = lagrangian
+                .interactions()
+                .iter()
+                .position(|x| x == interaction);
+            canvas.put(p, &vertex(k));
+        }
+    }
+    canvas.render()
+}
+
+/// An internal vertex shows the number of its interaction, circled while there is a glyph for it.
+fn vertex(k: Option<usize>) -> String {
+    match k {
+        Some(k) if k < 20 => char::from_u32(0x2460 + k as u32).unwrap().to_string(),
+        _ => String::from("●"),
+    }
+}
+
+/// Self-loops are drawn as a rounded box touching the vertex, which reads better than a circle
+/// this small.
+fn snail(canvas: &mut Canvas, line: &Line, strokes: [&'static str; 4], a: Point, dir: f64) {
+    const R: f64 = 4.0;
+    let [horizontal, vertical, _, _] = strokes;
+    let (ux, uy) = (dir.cos(), dir.sin());
+    let scale = R / ux.abs().max(uy.abs());
+    let c = (a.0 + ux * scale, a.1 + uy * scale);
+    let (x0, x1, y0, y1) = (c.0 - R, c.0 + R, c.1 - R, c.1 + R);
+    let mut x = x0 + 1.0;
+    while x < x1 {
+        canvas.put((x, y0), horizontal);
+        canvas.put((x, y1), horizontal);
+        x += 1.0;
+    }
+    let mut y = y0 + ASPECT;
+    while y < y1 {
+        canvas.put((x0, y), vertical);
+        canvas.put((x1, y), vertical);
+        y += ASPECT;
+    }
+    canvas.put((x0, y0), "╭");
+    canvas.put((x1, y0), "╮");
+    canvas.put((x0, y1), "╰");
+    canvas.put((x1, y1), "╯");
+    if let Some(forward) = line.arrow() {
+        let far = (c.0 + ux * scale, c.1 + uy * scale);
+        let (dx, dy) = if forward { (-uy, ux) } else { (uy, -ux) };
+        canvas.put(far, arrow(dx, dy));
+    }
+}
+
+/// The interactions the vertices are numbered by, then every diagram under its caption.
+pub fn listing<'a>(
+    diagrams: impl IntoIterator<Item = (String, &'a Diagram)>,
+    lagrangian: &CheckedLagrangian,
+) -> String {
+    let mut res = String::new();
+    for (k, x) in lagrangian.interactions().iter().enumerate() {
+        writeln!(res, "{} {}", vertex(Some(k)), plain(&x.to_string())).unwrap();
+    }
+    for ((caption, d), k) in diagrams.into_iter().zip(1..) {
+        writeln!(res).unwrap();
+        writeln!(res, "#{} {}", k, caption).unwrap();
+        res.push_str(&diagram(d, lagrangian));
+    }
+    res
+}
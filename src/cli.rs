@@ -1,197 +1,232 @@
-use clap::Parser;
-use std::collections::VecDeque;
+use clap::{error::ErrorKind, ArgGroup, CommandFactory, Parser, ValueEnum};
+use std::{
+    fs, io,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 
-use crate::{field::*, lagrangian::*};
+use crate::{diagram::*, json, lagrangian::*, parser::*};
 
 /// Produce Feynman diagrams from Lagrangian
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(group(ArgGroup::new("order").args(["n", "loops"])))]
 struct Cli {
-    /// Lagrangian, without coefficients
-    #[arg(short, long)]
+    /// Lagrangian, e.g. "-\frac{\lambda}{4!}\phi^4 + g\bar\psi\psi\phi"
+    #[arg(short, long, allow_hyphen_values = true)]
     lagrangian: Option<String>,
 
+    /// Read the Lagrangian, with field declarations and `%` comments, from this file, or from
+    /// stdin if it is `-`
+    #[arg(short, long, conflicts_with = "lagrangian")]
+    model: Option<PathBuf>,
+
+    /// Process, e.g. "\bar\psi \psi -> \phi \phi", or external legs without "->"
+    #[arg(short, long, required_unless_present = "load")]
+    process: Option<String>,
+
     /// Maximum order of the diagram
-    #[arg(short)]
-    n: u32,
+    #[arg(short, required_unless_present_any = ["loops", "load"])]
+    n: Option<u32>,
+
+    /// Only draw diagrams with exactly this many loops
+    #[arg(short = 'L', long)]
+    loops: Option<u32>,
+
+    /// Only draw diagrams of exactly order n
+    #[arg(long)]
+    exact: bool,
+
+    /// Merge topologically identical diagrams
+    #[arg(long)]
+    dedup: bool,
+
+    /// Only keep one-particle-irreducible diagrams
+    #[arg(long = "1pi")]
+    one_pi: bool,
+
+    /// Drop diagrams with subgraphs attached by a single line
+    #[arg(long)]
+    no_tadpoles: bool,
+
+    /// Drop diagrams with self-energy insertions on external legs
+    #[arg(long)]
+    no_external_self_energies: bool,
+
+    /// Drop diagrams with self-loops
+    #[arg(long)]
+    no_snails: bool,
+
+    /// Stop after this many diagrams of each order
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Write the output to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Also compile a tikz-feynman document into this PDF
+    #[arg(long)]
+    pdf: Option<PathBuf>,
+
+    /// LuaLaTeX executable used by --pdf
+    #[arg(long, default_value = "lualatex")]
+    lualatex: PathBuf,
+
+    /// Render diagrams saved with --format json instead of generating them
+    #[arg(long, conflicts_with_all = ["lagrangian", "model", "process", "order"])]
+    load: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Format {
+    /// Plain listing of every diagram
+    Text,
+    /// Box-drawing pictures for the terminal
+    Ascii,
+    /// The Lagrangian and every diagram, for other tools or --load
+    Json,
+    /// LuaLaTeX document using tikz-feynman
+    Tikz,
+    /// Graphviz, one graph per diagram
+    Dot,
+    /// Graphviz, one graph with a cluster per diagram
+    DotClusters,
+    /// SVG sheet with every diagram
+    Svg,
+    /// One SVG file per diagram, written into the --output directory
+    SvgDir,
 }
 
-pub fn parse() -> (CheckedLagrangian, u32) {
+pub struct Config {
+    pub lagrangian: CheckedLagrangian,
+    pub process: Vec<Vertex>,
+    pub orders: RangeInclusive<u32>,
+    pub loops: Option<u32>,
+    pub dedup: bool,
+    pub filters: Filters,
+    pub limit: Option<usize>,
+    pub format: Format,
+    pub output: Option<PathBuf>,
+    pub pdf: Option<PathBuf>,
+    pub lualatex: PathBuf,
+    pub saved: Option<Vec<json::Entry>>,
+}
+
+pub fn parse() -> Config {
     let cli = Cli::parse();
+    if cli.format == Format::SvgDir && cli.output.is_none() {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--format svg-dir needs an --output directory",
+            )
+            .exit();
+    }
+
+    if let Some(path) = &cli.load {
+        let saved = fs::read_to_string(path)
+            .map_err(|e| Cli::command().error(ErrorKind::Io, format!("{}: {}", path.display(), e)))
+            .and_then(|text| {
+                json::read(&text).map_err(|e| {
+                    Cli::command().error(
+                        ErrorKind::InvalidValue,
+                        format!("{}: {}", path.display(), e),
+                    )
+                })
+            })
+            .unwrap_or_else(|e| e.exit());
+        return Config {
+            lagrangian: saved.lagrangian,
+            process: vec![],
+            orders: RangeInclusive::new(1, 0),
+            loops: None,
+            dedup: false,
+            filters: Filters::default(),
+            limit: None,
+            format: cli.format,
+            output: cli.output,
+            pdf: cli.pdf,
+            lualatex: cli.lualatex,
+            saved: Some(saved.diagrams),
+        };
+    }
 
     let lag;
+    let mut source = None;
 
     if let Some(x) = cli.lagrangian {
         lag = x;
+    } else if let Some(path) = &cli.model {
+        lag = read_model(path)
+            .map_err(|e| Cli::command().error(ErrorKind::Io, format!("{}: {}", name(path), e)))
+            .unwrap_or_else(|e| e.exit());
+        source = Some(name(path));
     } else if true {
         lag = String::from(r"\phi\bar\psi\psi");
     } else {
         panic!("必须提供拉氏量");
     }
 
-    let mut lag: VecDeque<_> = lag.trim().chars().collect();
-    lag.push_front('+');
-
-    let res = || -> Result<UncheckedLagrangian, ()> {
-        let mut res = UncheckedLagrangian::new();
-        let drop_whitespace = |seq: &mut VecDeque<char>| {
-            while let Some(c) = seq.front() {
-                if !c.is_whitespace() {
-                    break;
-                }
-                seq.pop_front();
-            }
-        };
-        loop {
-            drop_whitespace(&mut lag);
-            match lag.pop_front() {
-                None => break,
-                Some('%') => break,
-                Some('+') => (),
-                Some(_) => return Err(()),
-            };
-            let mut factors = vec![];
-            loop {
-                let get_token = |seq: &mut VecDeque<char>| -> Result<Option<String>, ()> {
-                    drop_whitespace(seq);
-                    match seq.pop_front() {
-                        None => Ok(None),
-                        Some('{') | Some('}') => Err(()),
-                        Some('+') => {
-                            seq.push_front('+');
-                            Ok(None)
-                        }
-                        Some('\\') => {
-                            if seq.front().is_some() {
-                                let mut res = String::from('\\');
-                                let c = seq.front().unwrap();
-                                if c.is_ascii_alphabetic() {
-                                    while let Some(c) = seq.front() {
-                                        if !c.is_ascii_alphabetic() {
-                                            break;
-                                        }
-                                        res.push(*c);
-                                        seq.pop_front();
-                                    }
-                                } else {
-                                    res.push(*c);
-                                    seq.pop_front();
-                                }
-                                Ok(Some(res))
-                            } else {
-                                Err(())
-                            }
-                        }
-                        Some(c) => Ok(Some(String::from(c))),
-                    }
-                };
-                let token = if let Some(t) = get_token(&mut lag)? {
-                    t
-                } else {
-                    break;
-                };
-                let (token, bar) = if token == "\\bar" {
-                    if let Some(t) = get_token(&mut lag)? {
-                        (t, true)
-                    } else {
-                        return Err(());
-                    }
-                } else {
-                    (token, false)
-                };
-
-                let get_block = |seq: &mut VecDeque<char>| -> Result<String, ()> {
-                    drop_whitespace(seq);
-                    Ok(match seq.pop_front() {
-                        None | Some('}') => return Err(()),
-                        Some('\\') => {
-                            if seq.front().is_some() {
-                                let mut res = String::from('\\');
-                                let c = seq.front().unwrap();
-                                if c.is_ascii_alphabetic() {
-                                    while let Some(c) = seq.front() {
-                                        if !c.is_ascii_alphabetic() {
-                                            break;
-                                        }
-                                        res.push(*c);
-                                        seq.pop_front();
-                                    }
-                                } else {
-                                    res.push(*c);
-                                    seq.pop_front();
-                                }
-                                res
-                            } else {
-                                return Err(());
-                            }
-                        }
-                        Some('{') => {
-                            let mut res = String::from('{');
-                            let mut layer = 1;
-                            while let Some(c) = seq.pop_front() {
-                                match c {
-                                    '{' => layer += 1,
-                                    '}' => layer -= 1,
-                                    _ => (),
-                                };
-                                res.push(c);
-                                if layer == 0 {
-                                    break;
-                                }
-                            }
-                            if layer != 0 {
-                                return Err(());
-                            }
-                            res
-                        }
-                        Some(c) => String::from(c),
-                    })
-                };
-                drop_whitespace(&mut lag);
-                let subscript = if lag.front().is_some_and(|c| c == &'_') {
-                    lag.pop_front();
-                    Some(get_block(&mut lag)?)
-                } else {
-                    None
-                };
-                drop_whitespace(&mut lag);
-                let mut superscript = if lag.front().is_some_and(|c| c == &'^') {
-                    lag.pop_front();
-                    Some(get_block(&mut lag)?)
-                } else {
-                    None
-                };
-                let num: u8 =
-                    if let Ok(v) = superscript.clone().unwrap_or(String::from("1")).parse() {
-                        superscript = None;
-                        v
-                    } else {
-                        1
-                    };
-
-                let kind = match token.as_str() {
-                    "\\phi" => RealScalar,
-                    "\\varphi" => ComplexScalar(bar),
-                    "A" => RealVector,
-                    "F" => ComplexVector(bar),
-                    "\\psi" => Spinor(bar),
-                    _ => return Err(()),
-                };
-                let mut name = token;
-                if let Some(sub) = subscript {
-                    name.push('_');
-                    name.push_str(&sub);
-                }
-                if let Some(sup) = superscript {
-                    name.push('^');
-                    name.push_str(&sup);
-                }
-                factors.append(&mut vec![Field::new(kind, &name); num as usize])
-            }
-            res.push(Interaction::new(factors.into_iter()));
-        }
-        Ok(res)
-    }();
-
-    (CheckedLagrangian::new(res.expect("拉氏量格式有误")), cli.n)
+    let invalid = |message: String| -> ! {
+        Cli::command()
+            .error(ErrorKind::InvalidValue, message)
+            .exit()
+    };
+    let (lagrangian, symbols) = parse_lagrangian(&lag).unwrap_or_else(|e| match &source {
+        Some(name) => invalid(format!("{}: {}", name, e.render(&lag))),
+        None => invalid(e.render(&lag)),
+    });
+    let text = cli.process.unwrap();
+    let process =
+        parse_process(&lagrangian, &symbols, &text).unwrap_or_else(|e| invalid(e.render(&text)));
+
+    let orders = match (cli.n, cli.loops) {
+        (Some(n), _) if cli.exact => n..=n,
+        (Some(n), _) => 0..=n,
+        // every interaction has at least three legs, so by Euler's formula each vertex adds at
+        // least one to 2L - 2 + E
+        (None, Some(l)) => 0..=(2 * l + process.len() as u32).saturating_sub(2),
+        (None, None) => unreachable!(),
+    };
+
+    Config {
+        lagrangian,
+        process,
+        orders,
+        loops: cli.loops,
+        dedup: cli.dedup,
+        filters: Filters {
+            one_particle_irreducible: cli.one_pi,
+            no_tadpoles: cli.no_tadpoles,
+            no_external_self_energies: cli.no_external_self_energies,
+            no_snails: cli.no_snails,
+        },
+        limit: cli.limit,
+        format: cli.format,
+        output: cli.output,
+        pdf: cli.pdf,
+        lualatex: cli.lualatex,
+        saved: None,
+    }
+}
+
+fn read_model(path: &Path) -> io::Result<String> {
+    if path == Path::new("-") {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+fn name(path: &Path) -> String {
+    if path == Path::new("-") {
+        String::from("<stdin>")
+    } else {
+        path.display().to_string()
+    }
 }
@@ -1,8 +1,15 @@
-use std::fmt::Display;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
-use crate::{field::*, lagrangian::*};
+use crate::{field::*, lagrangian::*, tikz};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Inout {
     In,
     Out,
@@ -11,16 +18,19 @@ pub enum Inout {
 
 pub use Inout::*;
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
 enum Unique {
     Nah,
     Yeah,
+    // saved diagrams are complete
+    #[default]
     Used,
 }
 
 use Unique::*;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 enum VertexKind {
     External { field: Field, inout: Inout },
     Internal { interaction: Interaction },
@@ -28,10 +38,26 @@ enum VertexKind {
 
 use VertexKind::*;
 
-#[derive(Clone, Debug)]
+type Port = (usize, Field, Option<(usize, usize)>);
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+enum Label {
+    External(usize),
+    Internal(Interaction),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Canonical {
+    labels: Vec<Label>,
+    lines: Vec<(usize, usize, Field)>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Vertex {
+    #[serde(flatten)]
     kind: VertexKind,
     adj: Vec<Option<(usize, usize)>>,
+    #[serde(skip)]
     uniq: Unique,
 }
 
@@ -64,6 +90,35 @@ impl Vertex {
         }
     }
 
+    fn label(&self, i: usize) -> Label {
+        match &self.kind {
+            External { field: _, inout: _ } => Label::External(i),
+            Internal { interaction } => Label::Internal(interaction.clone()),
+        }
+    }
+
+    pub fn interaction(&self) -> Option<&Interaction> {
+        match &self.kind {
+            External { field: _, inout: _ } => None,
+            Internal { interaction } => Some(interaction),
+        }
+    }
+
+    pub fn direction(&self) -> Option<Inout> {
+        match &self.kind {
+            External { field: _, inout } => Some(*inout),
+            Internal { interaction: _ } => None,
+        }
+    }
+
+    pub fn particle(&self) -> Option<Field> {
+        match &self.kind {
+            External { field, inout: Out } => Some(field.anti()),
+            External { field, inout: _ } => Some(field.clone()),
+            Internal { interaction: _ } => None,
+        }
+    }
+
     fn sign(&self) -> Vec<Field> {
         match &self.kind {
             External { field, inout: _ } => vec![field.clone()],
@@ -75,7 +130,7 @@ impl Vertex {
         self.adj.iter().map(|&x| x.map_or(1, |_| 0)).sum()
     }
 
-    fn enumerate_zipped(&self) -> Vec<(usize, Field, Option<(usize, usize)>)> {
+    fn enumerate_zipped(&self) -> Vec<Port> {
         self.sign()
             .into_iter()
             .zip(self.adj.clone())
@@ -94,17 +149,443 @@ impl Vertex {
 }
 
 #[derive(Clone, Debug)]
+pub struct Line {
+    pub from: usize,
+    pub to: usize,
+    pub field: Field,
+}
+
+impl Line {
+    /// Whether the charge arrow points from `from` to `to`, following tikz-feynman's styles.
+    pub fn arrow(&self) -> Option<bool> {
+        match self.field.kind() {
+            RealScalar | RealVector => None,
+            ComplexScalar(t) | ComplexVector(t) => Some(t),
+            Spinor(t) => Some(!t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Filters {
+    pub one_particle_irreducible: bool,
+    pub no_tadpoles: bool,
+    pub no_external_self_energies: bool,
+    pub no_snails: bool,
+}
+
+impl Filters {
+    pub fn accepts(&self, d: &Diagram) -> bool {
+        !(self.one_particle_irreducible && !d.is_one_particle_irreducible()
+            || self.no_tadpoles && d.has_tadpole()
+            || self.no_external_self_energies && d.has_external_self_energy()
+            || self.no_snails && d.has_snail())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "Unchecked")]
 pub struct Diagram {
     vertices: Vec<Vertex>,
+    #[serde(skip)]
     left: usize,
 }
 
+#[derive(Deserialize)]
+struct Unchecked {
+    vertices: Vec<Vertex>,
+}
+
+impl TryFrom<Unchecked> for Diagram {
+    type Error = String;
+
+    /// Only complete diagrams can be loaded: every port must be joined to a port of the
+    /// conjugate field that points back.
+    fn try_from(Unchecked { vertices }: Unchecked) -> Result<Self, String> {
+        for (i, u) in vertices.iter().enumerate() {
+            let signs = u.sign();
+            if signs.len() != u.adj.len() {
+                return Err(format!(
+                    "vertex {} has {} ports but {} fields",
+                    i,
+                    u.adj.len(),
+                    signs.len()
+                ));
+            }
+            for (ki, a) in u.adj.iter().enumerate() {
+                let (j, kj) = a.ok_or_else(|| format!("port {} of vertex {} is open", ki, i))?;
+                let back = vertices
+                    .get(j)
+                    .and_then(|v| Some((*v.adj.get(kj)?, v.sign().get(kj)?.clone())));
+                match back {
+                    Some((Some(b), g)) if b == (i, ki) && g == signs[ki].anti() => (),
+                    _ => {
+                        return Err(format!(
+                            "port {} of vertex {} is not joined to a matching port",
+                            ki, i
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(Diagram::new(vertices))
+    }
+}
+
 impl Diagram {
     pub fn new(vertices: Vec<Vertex>) -> Self {
         let left = vertices.iter().map(|x| x.left()).sum();
         Self { vertices, left }
     }
 
+    pub fn generate<'a>(
+        process: &'a [Vertex],
+        interactions: &'a [Interaction],
+        order: usize,
+        loops: Option<usize>,
+    ) -> impl Iterator<Item = Self> + 'a {
+        interactions
+            .iter()
+            .combinations_with_replacement(order)
+            .map(|ints| {
+                let mut vertices = process.to_vec();
+                vertices.extend(ints.into_iter().map(|x| Vertex::internal(x.clone())));
+                Self::new(vertices)
+            })
+            .filter(|d| !d.vertices.is_empty() && d.is_saturable())
+            .filter(move |d| loops.is_none_or(|l| d.line_count() + 1 == d.vertices.len() + l))
+            .flat_map(|d| d.drawings())
+    }
+
+    pub fn loops(&self) -> usize {
+        (self.line_count() + 1).saturating_sub(self.vertices.len())
+    }
+
+    /// Internal lines, each counted once even if it starts and ends at the same vertex.
+    pub fn edges(&self) -> usize {
+        self.lines()
+            .iter()
+            .filter(|x| {
+                self.vertices[x.from].particle().is_none()
+                    && self.vertices[x.to].particle().is_none()
+            })
+            .count()
+    }
+
+    pub fn vertices(&self) -> usize {
+        self.vertices
+            .iter()
+            .filter(|u| u.particle().is_none())
+            .count()
+    }
+
+    /// Power of each coupling, sorted by name
+    pub fn coupling_order(&self) -> Vec<(&str, usize)> {
+        let mut res: Vec<_> = self
+            .vertices
+            .iter()
+            .filter_map(|u| u.interaction()?.coupling())
+            .counts()
+            .into_iter()
+            .collect();
+        res.sort();
+        res
+    }
+
+    pub fn nodes(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn lines(&self) -> Vec<Line> {
+        let mut res = vec![];
+        for (i, u) in self.vertices.iter().enumerate() {
+            let signs = u.sign();
+            for (ki, (j, kj)) in u.adj.iter().enumerate().map(|t| (t.0, t.1.unwrap())) {
+                if (i, ki) < (j, kj) {
+                    res.push(Line {
+                        from: i,
+                        to: j,
+                        field: signs[ki].clone(),
+                    });
+                }
+            }
+        }
+        res
+    }
+
+    fn line_count(&self) -> usize {
+        self.vertices.iter().map(|u| u.adj.len()).sum::<usize>() / 2
+    }
+
+    pub fn is_saturable(&self) -> bool {
+        let mut count: HashMap<Field, isize> = HashMap::new();
+        for (_, f, k) in self.vertices.iter().flat_map(|x| x.enumerate_zipped()) {
+            if k.is_none() {
+                *count.entry(f).or_default() += 1;
+            }
+        }
+        count.iter().all(|(f, &n)| {
+            let g = f.anti();
+            if f == &g {
+                n % 2 == 0
+            } else {
+                count.get(&g).is_some_and(|&m| m == n)
+            }
+        })
+    }
+
+    pub fn dedup(diagrams: impl IntoIterator<Item = Self>) -> Vec<Self> {
+        let mut seen: HashSet<Canonical> = HashSet::new();
+        diagrams
+            .into_iter()
+            .filter(|d| seen.insert(d.canonical()))
+            .collect()
+    }
+
+    pub fn canonical(&self) -> Canonical {
+        self.canonize().0
+    }
+
+    /// Symmetry factor, assuming every interaction is normalised by 1/n! for each n identical
+    /// factors, so that only the automorphisms of the graph remain.
+    pub fn symmetry_factor(&self) -> usize {
+        let mut factor = self.canonize().1;
+        let lines = self
+            .vertices
+            .iter()
+            .enumerate()
+            .flat_map(|(i, u)| {
+                let signs = u.sign();
+                u.adj.iter().enumerate().filter_map(move |(ki, x)| {
+                    let (j, kj) = x.unwrap();
+                    let f = signs[ki].clone();
+                    if (i, ki) >= (j, kj) {
+                        None
+                    } else if i < j || (i == j && f <= f.anti()) {
+                        Some((i, j, f))
+                    } else {
+                        Some((j, i, f.anti()))
+                    }
+                })
+            })
+            .counts();
+        for ((i, j, f), k) in lines {
+            factor *= (1..=k).product::<usize>();
+            if i == j && f == f.anti() {
+                factor *= 1 << k;
+            }
+        }
+        factor
+    }
+
+    /// Wick contractions of the external fields with n interactions that give this diagram: n!
+    /// orders of the vertices times k! for every k identical factors of an interaction, over the
+    /// symmetry factor.
+    pub fn contractions(&self) -> u128 {
+        let factorial = |n| (1..=n as u128).product::<u128>();
+        let mut res = factorial(self.vertices());
+        for x in self.vertices.iter().filter_map(|u| u.interaction()) {
+            res *= x
+                .factors()
+                .iter()
+                .counts()
+                .into_values()
+                .map(factorial)
+                .product::<u128>();
+        }
+        res / self.symmetry_factor() as u128
+    }
+
+    /// Canonical form and number of automorphisms, by individualisation and refinement: the
+    /// form is the least one over every order the search tree ends in, and each automorphism
+    /// leads to one more leaf with that form.
+    fn canonize(&self) -> (Canonical, usize) {
+        let signs: Vec<_> = self.vertices.iter().map(|x| x.sign()).collect();
+        let labels: Vec<_> = self
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(i, u)| u.label(i))
+            .collect();
+        let mut best = None;
+        let mut automorphisms = 0;
+        self.search(&signs, rank(&labels), &mut best, &mut automorphisms);
+        (best.unwrap(), automorphisms)
+    }
+
+    fn search(
+        &self,
+        signs: &[Vec<Field>],
+        colors: (Vec<usize>, usize),
+        best: &mut Option<Canonical>,
+        automorphisms: &mut usize,
+    ) {
+        let colors = self.refine(signs, colors);
+        let counts = colors.iter().counts();
+        if let Some(cell) = counts.iter().filter(|x| *x.1 > 1).map(|x| **x.0).min() {
+            // every vertex of the first cell that refinement cannot split takes its turn first
+            for v in (0..colors.len()).filter(|&v| colors[v] == cell) {
+                let split = (0..colors.len()).map(|u| (colors[u], u != v)).collect_vec();
+                self.search(signs, rank(&split), best, automorphisms);
+            }
+            return;
+        }
+
+        let mut order = vec![0; colors.len()];
+        for (i, &c) in colors.iter().enumerate() {
+            order[c] = i;
+        }
+        let mut lines = vec![];
+        for (i, u) in self.vertices.iter().enumerate() {
+            for (ki, (j, kj)) in u.adj.iter().enumerate().map(|t| (t.0, t.1.unwrap())) {
+                if (i, ki) < (j, kj) {
+                    let a = (colors[i], &signs[i][ki]);
+                    let b = (colors[j], &signs[j][kj]);
+                    let (a, b) = if a <= b { (a, b) } else { (b, a) };
+                    lines.push((a.0, b.0, a.1.clone()));
+                }
+            }
+        }
+        lines.sort();
+        let now = Canonical {
+            labels: order.iter().map(|&i| self.vertices[i].label(i)).collect(),
+            lines,
+        };
+        match best.as_ref().map(|x| now.cmp(x)) {
+            Some(Ordering::Greater) => (),
+            Some(Ordering::Equal) => *automorphisms += 1,
+            _ => {
+                *best = Some(now);
+                *automorphisms = 1;
+            }
+        }
+    }
+
+    /// Splits the colour classes until each vertex sees the same colours around it as every other
+    /// vertex of its class.
+    fn refine(
+        &self,
+        signs: &[Vec<Field>],
+        (mut colors, mut count): (Vec<usize>, usize),
+    ) -> Vec<usize> {
+        loop {
+            let signatures: Vec<_> = self
+                .vertices
+                .iter()
+                .enumerate()
+                .map(|(i, u)| {
+                    let around = u
+                        .adj
+                        .iter()
+                        .enumerate()
+                        .map(|(ki, x)| {
+                            let (j, kj) = x.unwrap();
+                            (&signs[i][ki], colors[j], &signs[j][kj])
+                        })
+                        .sorted()
+                        .collect_vec();
+                    (colors[i], around)
+                })
+                .collect();
+            let (next, next_count) = rank(&signatures);
+            if next_count == count {
+                return colors;
+            }
+            (colors, count) = (next, next_count);
+        }
+    }
+
+    /// Sign of the permutation that brings every contracted pair of Grassmann fields next to
+    /// each other as ψ ψ̄, in the order they appear in the external legs and interactions. The
+    /// (-1) of every closed fermion loop is already contained in it.
+    pub fn fermion_sign(&self) -> isize {
+        let ports = self.fermion_ports();
+        let index: HashMap<_, _> = ports.iter().enumerate().map(|(k, &p)| (p, k)).collect();
+        let mut order = vec![];
+        for &(i, ki) in &ports {
+            let (j, kj) = self.vertices[i].adj[ki].unwrap();
+            if (i, ki) < (j, kj) {
+                let (a, b) = if self.vertices[i].sign()[ki].kind() == Spinor(false) {
+                    ((i, ki), (j, kj))
+                } else {
+                    ((j, kj), (i, ki))
+                };
+                order.push(index[&a]);
+                order.push(index[&b]);
+            }
+        }
+        let inversions = order
+            .iter()
+            .tuple_combinations()
+            .filter(|(a, b)| a > b)
+            .count();
+        if inversions % 2 == 0 {
+            1
+        } else {
+            -1
+        }
+    }
+
+    pub fn fermion_loops(&self) -> usize {
+        let ports = self.fermion_ports();
+        let index: HashMap<_, _> = ports.iter().enumerate().map(|(k, &p)| (p, k)).collect();
+        let mut next = vec![vec![]; ports.len()];
+        for (k, &(i, ki)) in ports.iter().enumerate() {
+            next[k].push(index[&self.vertices[i].adj[ki].unwrap()]);
+        }
+        // the fermion line runs from the k-th ψ̄ of a vertex to its k-th ψ, whichever order the
+        // interaction writes them in and whether or not it changes the flavour
+        for (i, u) in self.vertices.iter().enumerate() {
+            let signs = &u.sign();
+            let ports = |bar| (0..signs.len()).filter(move |&k| signs[k].kind() == Spinor(bar));
+            for (kb, ki) in ports(true).zip(ports(false)) {
+                let (a, b) = (index[&(i, kb)], index[&(i, ki)]);
+                next[a].push(b);
+                next[b].push(a);
+            }
+        }
+
+        let mut vis = vec![false; ports.len()];
+        let mut loops = 0;
+        for k in 0..ports.len() {
+            if vis[k] {
+                continue;
+            }
+            vis[k] = true;
+            let mut stack = vec![k];
+            let mut closed = true;
+            while let Some(u) = stack.pop() {
+                if let External { field: _, inout: _ } = self.vertices[ports[u].0].kind {
+                    closed = false;
+                }
+                for &v in &next[u] {
+                    if !vis[v] {
+                        vis[v] = true;
+                        stack.push(v);
+                    }
+                }
+            }
+            if closed {
+                loops += 1;
+            }
+        }
+        loops
+    }
+
+    fn fermion_ports(&self) -> Vec<(usize, usize)> {
+        self.vertices
+            .iter()
+            .enumerate()
+            .flat_map(|(i, u)| {
+                u.sign()
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, f)| matches!(f.kind(), Spinor(_)))
+                    .map(move |(ki, _)| (i, ki))
+            })
+            .collect()
+    }
+
     pub fn is_connected(&self) -> bool {
         if self.left != 0 {
             for i in 0..self.vertices.len() {
@@ -118,7 +599,7 @@ impl Diagram {
                     let u = &self.vertices[u];
                     cnt += 1;
                     for v in &u.adj {
-                        if let Some((v, _)) = v.clone() {
+                        if let Some((v, _)) = *v {
                             if !vis[v] {
                                 vis[v] = true;
                                 stack.push(v);
@@ -153,13 +634,92 @@ impl Diagram {
         cnt == self.vertices.len()
     }
 
-    pub fn draw(self) -> Vec<Self> {
-        if self.left % 2 != 0 || !self.is_connected() {
-            return vec![];
+    pub fn is_one_particle_irreducible(&self) -> bool {
+        self.bridges().is_empty()
+    }
+
+    pub fn has_tadpole(&self) -> bool {
+        self.bridges()
+            .into_iter()
+            .any(|(i, ki)| self.bridge_sides(i, ki).contains(&0))
+    }
+
+    pub fn has_external_self_energy(&self) -> bool {
+        self.bridges()
+            .into_iter()
+            .any(|(i, ki)| self.bridge_sides(i, ki).contains(&1))
+    }
+
+    pub fn has_snail(&self) -> bool {
+        self.vertices
+            .iter()
+            .enumerate()
+            .any(|(i, u)| u.adj.iter().any(|x| x.is_some_and(|(j, _)| i == j)))
+    }
+
+    fn bridge_sides(&self, i: usize, ki: usize) -> [usize; 2] {
+        let vis = self.reachable(i, (i, ki));
+        let externals = self
+            .vertices
+            .iter()
+            .zip(vis)
+            .filter(|(u, _)| u.particle().is_some());
+        let (near, far): (Vec<_>, Vec<_>) = externals.partition(|(_, v)| *v);
+        [near.len(), far.len()]
+    }
+
+    fn bridges(&self) -> Vec<(usize, usize)> {
+        let mut res = vec![];
+        for (i, u) in self.vertices.iter().enumerate() {
+            if u.particle().is_some() {
+                continue;
+            }
+            for (ki, x) in u.adj.iter().enumerate() {
+                let (j, _) = x.unwrap();
+                if i >= j || self.vertices[j].particle().is_some() {
+                    continue;
+                }
+                if !self.reachable(i, (i, ki))[j] {
+                    res.push((i, ki));
+                }
+            }
         }
-        if self.left == 0 {
-            return vec![self];
+        res
+    }
+
+    fn reachable(&self, start: usize, cut: (usize, usize)) -> Vec<bool> {
+        let (i, ki) = cut;
+        let cut = [(i, ki), self.vertices[i].adj[ki].unwrap()];
+        let mut vis = vec![false; self.vertices.len()];
+        let mut stack = vec![];
+        vis[start] = true;
+        stack.push(start);
+        while let Some(u) = stack.pop() {
+            for (ku, v) in self.vertices[u].adj.iter().enumerate() {
+                if cut.contains(&(u, ku)) {
+                    continue;
+                }
+                if let Some((v, _)) = *v {
+                    if !vis[v] {
+                        vis[v] = true;
+                        stack.push(v);
+                    }
+                }
+            }
         }
+        vis
+    }
+
+    #[allow(dead_code)]
+    pub fn draw(self) -> Vec<Self> {
+        self.drawings().collect()
+    }
+
+    pub fn drawings(self) -> Drawings {
+        Drawings { stack: vec![self] }
+    }
+
+    fn expand(&self) -> Vec<Self> {
         let vs = &self.vertices;
         let mut candidates: Vec<Self> = vec![];
         if let Some(i) = vs.iter().position(|x| x.uniq == Yeah) {
@@ -174,252 +734,257 @@ impl Diagram {
                 let f: Field = zipped.clone().next().unwrap().1.clone();
                 let indices: Vec<usize> = zipped.filter(|t| t.1 == f).map(|t| t.0).collect();
                 let g = f.anti();
-                match indices.len() {
-                    1 => {
-                        let ki = indices[0];
-                        for (j, v) in vs.iter().enumerate() {
-                            if f == g && i == j {
-                                continue;
-                            }
-                            let kjs = v.ports(&g);
-                            if kjs.is_empty() {
-                                continue;
-                            }
-                            let kj = kjs[0];
-                            let mut now = self.clone();
-                            now.vertices[i].adj[ki] = Some((j, kj));
-                            now.vertices[j].adj[kj] = Some((i, ki));
-                            now.vertices[j].uniq = Yeah;
-                            now.left -= 2;
-                            candidates.push(now);
-                            if v.uniq == Nah {
-                                break;
-                            }
-                        }
-                    }
-                    2 => {
-                        let (ki0, ki1) = (indices[0], indices[1]);
-                        for (j, v) in vs.iter().enumerate() {
-                            if f == g && i == j {
-                                // self loop
-                                let mut now = self.clone();
-                                now.vertices[i].adj[ki0] = Some((i, ki1));
-                                now.vertices[i].adj[ki1] = Some((i, ki0));
-                                now.left -= 2;
-                                candidates.push(now);
-                                continue;
-                            }
-                            let kjs = v.ports(&g);
-                            if kjs.len() <= 1 {
-                                continue;
-                            }
-                            let (kj0, kj1) = (kjs[0], kjs[1]);
-                            let mut now = self.clone();
-                            now.vertices[i].adj[ki0] = Some((j, kj0));
-                            now.vertices[i].adj[ki1] = Some((j, kj1));
-                            now.vertices[j].adj[kj0] = Some((i, ki0));
-                            now.vertices[j].adj[kj1] = Some((i, ki1));
-                            now.vertices[j].uniq = Yeah;
-                            now.left -= 4;
-                            candidates.push(now);
-                            if v.uniq == Nah {
-                                break;
-                            }
-                        }
-                        for (j1, v1) in vs.iter().enumerate() {
-                            if f == g && i == j1 {
-                                continue;
-                            }
-                            let kj1s = v1.ports(&g);
-                            if kj1s.is_empty() {
-                                continue;
-                            }
-                            let kj1 = kj1s[0];
-                            for (j0, v0) in vs.iter().enumerate().take(j1) {
-                                if f == g && i == j0 {
-                                    continue;
-                                }
-                                let kj0s = v0.ports(&g);
-                                if kj0s.is_empty() {
-                                    continue;
-                                }
-                                let kj0 = kj0s[0];
-                                let mut now = self.clone();
-                                now.vertices[i].adj[ki0] = Some((j0, kj0));
-                                now.vertices[i].adj[ki1] = Some((j1, kj1));
-                                now.vertices[j0].adj[kj0] = Some((i, ki0));
-                                now.vertices[j1].adj[kj1] = Some((i, ki1));
-                                now.left -= 4;
-                                candidates.push(now);
-                            }
+                let k = indices.len();
+                // untouched vertices of the same interaction are interchangeable, so only
+                // distributions that do not increase along each such class are tried
+                let slots: Vec<_> = vs
+                    .iter()
+                    .enumerate()
+                    .map(|(j, v)| {
+                        let avail = if f == g && i == j {
+                            0
+                        } else {
+                            v.ports(&g).len()
+                        };
+                        let prev = (0..j).rev().find(|&p| {
+                            v.uniq == Nah && vs[p].uniq == Nah && vs[p].label(p) == v.label(j)
+                        });
+                        (avail, prev)
+                    })
+                    .collect();
+                let loops = if f == g { k / 2 } else { 0 };
+                for s in 0..=loops {
+                    for counts in distribute(&slots, k - 2 * s) {
+                        let mut now = self.clone();
+                        let mut kis = indices.iter().copied();
+                        for _ in 0..s {
+                            // self loop
+                            let (ki0, ki1) = (kis.next().unwrap(), kis.next().unwrap());
+                            now.vertices[i].adj[ki0] = Some((i, ki1));
+                            now.vertices[i].adj[ki1] = Some((i, ki0));
                         }
-                    }
-                    3 => {
-                        let (ki0, ki1, ki2) = (indices[0], indices[1], indices[2]);
-                        for (j, v) in vs.iter().enumerate() {
-                            if f == g && i == j {
-                                continue;
+                        for (j, &c) in counts.iter().enumerate() {
+                            for &kj in &vs[j].ports(&g)[..c] {
+                                let ki = kis.next().unwrap();
+                                now.vertices[i].adj[ki] = Some((j, kj));
+                                now.vertices[j].adj[kj] = Some((i, ki));
                             }
-                            let kjs = v.ports(&g);
-                            if f == g && !kjs.is_empty() {
-                                // self loop and a line
-                                let kj = kjs[0];
-                                let mut now = self.clone();
-                                now.vertices[i].adj[ki0] = Some((i, ki1));
-                                now.vertices[i].adj[ki1] = Some((i, ki0));
-                                now.vertices[i].adj[ki2] = Some((j, kj));
-                                now.vertices[j].adj[kj] = Some((i, ki2));
+                            if c > 0 && now.vertices[j].uniq == Nah {
                                 now.vertices[j].uniq = Yeah;
-                                now.left -= 4;
-                                candidates.push(now);
-                            }
-                            if kjs.len() <= 2 {
-                                continue;
-                            }
-                            let (kj0, kj1, kj2) = (kjs[0], kjs[1], kjs[2]);
-                            let mut now = self.clone();
-                            now.vertices[i].adj[ki0] = Some((j, kj0));
-                            now.vertices[i].adj[ki1] = Some((j, kj1));
-                            now.vertices[i].adj[ki2] = Some((j, kj2));
-                            now.vertices[j].adj[kj0] = Some((i, ki0));
-                            now.vertices[j].adj[kj1] = Some((i, ki1));
-                            now.vertices[j].adj[kj2] = Some((i, ki2));
-                            now.vertices[j].uniq = Yeah;
-                            now.left -= 6;
-                            candidates.push(now);
-                            if v.uniq == Nah {
-                                break;
-                            }
-                        }
-                        for (j1, v1) in vs.iter().enumerate() {
-                            if f == g && i == j1 {
-                                continue;
-                            }
-                            let kj1s = v1.ports(&g);
-                            if kj1s.len() <= 1 {
-                                continue;
-                            }
-                            let (kj10, kj11) = (kj1s[0], kj1s[1]);
-                            for (j0, v0) in vs.iter().enumerate().take(j1) {
-                                if f == g && i == j0 {
-                                    continue;
-                                }
-                                let kj0s = v0.ports(&g);
-                                if kj0s.is_empty() {
-                                    continue;
-                                }
-                                let kj0 = kj0s[0];
-                                let mut now = self.clone();
-                                now.vertices[i].adj[ki0] = Some((j0, kj0));
-                                now.vertices[i].adj[ki1] = Some((j1, kj10));
-                                now.vertices[i].adj[ki2] = Some((j1, kj11));
-                                now.vertices[j0].adj[kj0] = Some((i, ki0));
-                                now.vertices[j1].adj[kj10] = Some((i, ki1));
-                                now.vertices[j1].adj[kj11] = Some((i, ki2));
-                                now.vertices[j0].uniq = Yeah;
-                                now.vertices[j1].uniq = Yeah;
-                                now.left -= 6;
-                                candidates.push(now);
-                            }
-                        }
-                        for (j2, v2) in vs.iter().enumerate() {
-                            if f == g && i == j2 {
-                                continue;
-                            }
-                            let kj2s = v2.ports(&g);
-                            if kj2s.is_empty() {
-                                continue;
-                            }
-                            let kj2 = kj2s[0];
-                            for (j1, v1) in vs.iter().enumerate().take(j2) {
-                                if f == g && i == j1 {
-                                    continue;
-                                }
-                                let kj1s = v1.ports(&g);
-                                if kj1s.is_empty() {
-                                    continue;
-                                }
-                                let kj1 = kj1s[0];
-                                for (j0, v0) in vs.iter().enumerate().take(j1) {
-                                    if f == g && i == j0 {
-                                        continue;
-                                    }
-                                    let kj0s = v0.ports(&g);
-                                    if kj0s.is_empty() {
-                                        continue;
-                                    }
-                                    let kj0 = kj0s[0];
-                                    let mut now = self.clone();
-                                    now.vertices[i].adj[ki0] = Some((j0, kj0));
-                                    now.vertices[i].adj[ki1] = Some((j1, kj1));
-                                    now.vertices[i].adj[ki2] = Some((j2, kj2));
-                                    now.vertices[j0].adj[kj0] = Some((i, ki0));
-                                    now.vertices[j1].adj[kj1] = Some((i, ki1));
-                                    now.vertices[j2].adj[kj2] = Some((i, ki2));
-                                    now.left -= 6;
-                                    candidates.push(now);
-                                }
                             }
                         }
+                        now.left -= 2 * (k - s);
+                        candidates.push(now);
                     }
-                    _ => unreachable!(),
                 }
             }
         } else {
-            let i = self.vertices.iter().position(|x| x.left() != 0).unwrap();
-            let u = &vs[i];
-            let ki = u.adj.iter().position(|x| x.is_none()).unwrap();
-            let f = u.sign()[ki].clone();
-            let g = f.anti();
-            for (j, v) in vs.iter().enumerate() {
-                let kjs = v.ports(&g);
-                if kjs.is_empty() {
-                    continue;
-                }
-                if f == g && i == j {
-                    if kjs.len() >= 2 {
-                        // self loop
-                        let kj = kjs[1];
-                        let mut now = self.clone();
-                        now.vertices[i].adj[ki] = Some((j, kj));
-                        now.vertices[j].adj[kj] = Some((i, ki));
-                        now.left -= 2;
-                        candidates.push(now);
-                    }
-                    continue;
-                }
-                let kj = kjs[0];
-                let mut now = self.clone();
-                now.vertices[i].adj[ki] = Some((j, kj));
-                now.vertices[j].adj[kj] = Some((i, ki));
-                now.left -= 2;
-                candidates.push(now);
+            // vacuum diagrams start with every vertex untouched, and the first one has to be
+            // in the diagram anyway
+            let mut now = self.clone();
+            now.vertices[0].uniq = Yeah;
+            candidates.push(now);
+        }
+        candidates
+    }
+}
+
+/// Dense ranks of the values, and how many distinct ones there are.
+fn rank<T: Ord>(xs: &[T]) -> (Vec<usize>, usize) {
+    let sorted: Vec<&T> = xs.iter().sorted().dedup().collect();
+    let ranks = xs.iter().map(|x| sorted.binary_search(&x).unwrap());
+    (ranks.collect(), sorted.len())
+}
+
+fn distribute(slots: &[(usize, Option<usize>)], total: usize) -> Vec<Vec<usize>> {
+    fn go(
+        slots: &[(usize, Option<usize>)],
+        total: usize,
+        now: &mut Vec<usize>,
+        res: &mut Vec<Vec<usize>>,
+    ) {
+        let j = now.len();
+        if j == slots.len() {
+            if total == 0 {
+                res.push(now.clone());
+            }
+            return;
+        }
+        let (avail, prev) = slots[j];
+        let max = prev.map_or(avail, |p| avail.min(now[p])).min(total);
+        for c in 0..=max {
+            now.push(c);
+            go(slots, total - c, now, res);
+            now.pop();
+        }
+    }
+
+    let mut res = vec![];
+    go(slots, total, &mut vec![], &mut res);
+    res
+}
+
+pub struct Drawings {
+    stack: Vec<Diagram>,
+}
+
+impl Iterator for Drawings {
+    type Item = Diagram;
+
+    fn next(&mut self) -> Option<Diagram> {
+        while let Some(d) = self.stack.pop() {
+            if !d.left.is_multiple_of(2) || !d.is_connected() {
+                continue;
+            }
+            if d.left == 0 {
+                return Some(d);
             }
+            self.stack.extend(d.expand().into_iter().rev());
         }
-        candidates.into_iter().flat_map(|d| d.draw()).collect()
+        None
     }
 }
 
 impl Display for Diagram {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for (i, u) in self.vertices.iter().enumerate() {
-            for (ki, (j, kj)) in u.adj.iter().enumerate().map(|t| (t.0, t.1.unwrap())) {
-                if (i, ki) < (j, kj) {
-                    let t = &u.sign()[ki];
-                    let mut text: String = match t.kind() {
-                        RealScalar => "scalar",
-                        ComplexScalar(x) => if x { "charged scalar" } else { "anti charged scalar" },
-                        RealVector => "boson",
-                        ComplexVector(x) => if x { "charged boson" } else { "anti charged boson" },
-                        Spinor(x) => if x { "anti fermion" } else { "fermion" },
-                    }.to_string();
-                    if i == j {
-                        text += ", min distance=2.5cm";
-                    }
-                    write!(f, "\t{0} -- [{2}] {1},\n", i, j, text)?;
-                }
+            if let Some(p) = u.particle() {
+                writeln!(f, "\t{} [particle=\\({}\\)],", i, p)?;
             }
         }
+        for Line { from, to, field } in self.lines() {
+            let mut text = tikz::style(field.kind()).to_string();
+            if from == to {
+                text += ", min distance=2.5cm";
+            }
+            writeln!(f, "\t{0} -- [{2}] {1},", from, to, text)?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    /// Distinct diagrams of exactly this order.
+    fn diagrams(lagrangian: &str, process: &str, order: usize) -> Vec<Diagram> {
+        let (lagrangian, symbols) = parse_lagrangian(lagrangian).unwrap();
+        let process = parse_process(&lagrangian, &symbols, process).unwrap();
+        let all = Diagram::generate(&process, lagrangian.interactions(), order, None);
+        Diagram::dedup(all)
+    }
+
+    #[test]
+    fn snail_is_one_propagator() {
+        let d = diagrams(r"-\frac{\lambda}{4!}\phi^4", r"\phi -> \phi", 1);
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].edges(), 1);
+        assert_eq!(d[0].loops(), 1);
+        assert_eq!(d[0].symmetry_factor(), 2);
+        // 4 · 3 ways to attach the external legs
+        assert_eq!(d[0].contractions(), 12);
+    }
+
+    #[test]
+    fn phi3_vacuum_topologies() {
+        // connected cubic multigraphs with 2, 4 and 6 vertices, where every vertex is alike
+        for (order, count) in [(2, 2), (4, 5), (6, 17)] {
+            assert_eq!(diagrams(r"-\frac{g}{3!}\phi^3", "", order).len(), count);
+        }
+    }
+
+    #[test]
+    fn phi3_two_loop_vacuum() {
+        let d = diagrams(r"-\frac{g}{3!}\phi^3", "", 2);
+        let factors = d.iter().map(|d| d.symmetry_factor()).sorted().collect_vec();
+        assert_eq!(factors, [8, 12]);
+        // of the 15 contractions of two φ³, 6 give the theta and 9 the dumbbell
+        let contractions = d.iter().map(|d| d.contractions()).sorted().collect_vec();
+        assert_eq!(contractions, [6, 9]);
+        assert!(d.iter().all(|d| d.loops() == 2 && d.edges() == 3));
+    }
+
+    #[test]
+    fn one_particle_irreducible() {
+        let phi3 = r"-\frac{g}{3!}\phi^3";
+        let d = diagrams(phi3, r"\phi -> \phi", 2);
+        let irreducible = d
+            .iter()
+            .filter(|d| d.is_one_particle_irreducible())
+            .collect_vec();
+        assert_eq!((d.len(), irreducible.len()), (2, 1));
+        assert!(!irreducible[0].has_snail());
+
+        // only the three boxes survive at one loop
+        let d = diagrams(phi3, r"\phi \phi -> \phi \phi", 4);
+        let filters = Filters {
+            one_particle_irreducible: true,
+            ..Default::default()
+        };
+        assert_eq!(d.iter().filter(|d| filters.accepts(d)).count(), 3);
+    }
+
+    #[test]
+    fn topology_filters() {
+        // the one-loop corrections to φφ → φφ: 3 boxes, 6 triangles, 3 bubbles and 3 tadpoles on
+        // the internal line, and 12 bubbles and 12 tadpoles on the external legs
+        let d = diagrams(r"-\frac{g}{3!}\phi^3", r"\phi \phi -> \phi \phi", 4);
+        let count = |only: fn(&mut Filters)| {
+            let mut filters = Filters::default();
+            only(&mut filters);
+            d.iter().filter(|d| filters.accepts(d)).count()
+        };
+        assert_eq!(count(|_| ()), 39);
+        assert_eq!(count(|x| x.no_tadpoles = true), 24);
+        assert_eq!(count(|x| x.no_external_self_energies = true), 15);
+        assert_eq!(count(|x| x.no_snails = true), 24);
+    }
+
+    #[test]
+    fn six_leg_vertex() {
+        let phi6 = r"-\frac{\lambda}{6!}\phi^6";
+        let tree = diagrams(phi6, r"\phi \phi \phi -> \phi \phi \phi", 1);
+        assert_eq!(tree.len(), 1);
+        assert_eq!((tree[0].loops(), tree[0].contractions()), (0, 720));
+
+        // three self-loops, pairing the six legs in 5 · 3 ways
+        let vacuum = diagrams(phi6, "", 1);
+        assert_eq!(vacuum.len(), 1);
+        assert_eq!(vacuum[0].symmetry_factor(), 48);
+        assert_eq!(vacuum[0].contractions(), 15);
+    }
+
+    #[test]
+    fn yukawa_self_energy() {
+        let d = diagrams(r"g\phi\bar\psi\psi", r"\phi -> \phi", 2);
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].loops(), 1);
+        assert_eq!(d[0].fermion_sign(), -1);
+        assert_eq!(d[0].fermion_loops(), 1);
+    }
+
+    #[test]
+    fn fermion_loops_ignore_factor_order() {
+        for lagrangian in [r"g\phi\bar\psi\psi", r"g\phi\psi\bar\psi"] {
+            let tree = diagrams(lagrangian, r"\psi \phi -> \psi \phi", 2);
+            assert_eq!(tree.len(), 2);
+            assert!(tree.iter().all(|d| d.fermion_loops() == 0));
+
+            let vacuum = diagrams(lagrangian, "", 2);
+            let loops = vacuum
+                .iter()
+                .map(|d| d.fermion_loops())
+                .sorted()
+                .collect_vec();
+            assert_eq!(loops, [1, 2]);
+        }
+
+        // the ψ̄ of one flavour and the ψ of another still make one line through the vertex
+        let charged = r"field e, \nu: spinor; field h: real scalar; y\bar\nu e h + h.c.";
+        let d = diagrams(charged, "h -> h", 2);
+        assert_eq!(d.len(), 2);
+        assert!(d.iter().all(|d| d.loops() == 1 && d.fermion_loops() == 1));
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt::Write;
+
+use crate::{diagram::*, field::*, lagrangian::*};
+
+const PALETTE: [&str; 6] = ["black", "red3", "blue3", "green4", "orange3", "purple3"];
+
+/// Massive lines are drawn thicker and labelled with their mass.
+pub fn style(line: &Line, lagrangian: &CheckedLagrangian) -> String {
+    let mut res = match line.field.kind() {
+        RealScalar | ComplexScalar(_) => "style=dashed",
+        RealVector | ComplexVector(_) => "color=\"black:invis:black\"",
+        Spinor(_) => "style=solid",
+    }
+    .to_string();
+    match line.arrow() {
+        Some(true) => res += ", dir=forward",
+        Some(false) => res += ", dir=back",
+        None => (),
+    }
+    if let Some(mass) = lagrangian.mass(&line.field) {
+        res += &format!(", penwidth=2, label=\"{}\"", escape(mass));
+    }
+    res
+}
+
+/// One `graph` per diagram.
+pub fn graphs<'a>(
+    diagrams: impl IntoIterator<Item = (String, &'a Diagram)>,
+    lagrangian: &CheckedLagrangian,
+) -> String {
+    let mut res = String::new();
+    for (k, (caption, d)) in diagrams.into_iter().enumerate() {
+        writeln!(res, "graph diagram_{} {{", k + 1).unwrap();
+        writeln!(res, "\tlabel=\"{}\";", escape(&caption)).unwrap();
+        res.push_str(&body(d, "v", lagrangian, "\t"));
+        writeln!(res, "}}").unwrap();
+    }
+    res
+}
+
+/// A single `graph` with one cluster per diagram.
+pub fn clusters<'a>(
+    diagrams: impl IntoIterator<Item = (String, &'a Diagram)>,
+    lagrangian: &CheckedLagrangian,
+) -> String {
+    let mut res = String::from("graph diagrams {\n");
+    for (k, (caption, d)) in diagrams.into_iter().enumerate() {
+        writeln!(res, "\tsubgraph cluster_{} {{", k + 1).unwrap();
+        writeln!(res, "\t\tlabel=\"{}\";", escape(&caption)).unwrap();
+        let prefix = format!("d{}_v", k + 1);
+        res.push_str(&body(d, &prefix, lagrangian, "\t\t"));
+        writeln!(res, "\t}}").unwrap();
+    }
+    res.push_str("}\n");
+    res
+}
+
+fn body(d: &Diagram, prefix: &str, lagrangian: &CheckedLagrangian, indent: &str) -> String {
+    let mut res = String::new();
+    for (i, u) in d.nodes().iter().enumerate() {
+        write!(res, "{}{}{} [", indent, prefix, i).unwrap();
+        if let Some(p) = u.particle() {
+            write!(res, "shape=point, xlabel=\"{}\"", escape(&p.to_string())).unwrap();
+        } else {
+            let interaction = u.interaction().unwrap();
+            let k = lagrangian
+                .interactions()
+                .iter()
+                .position(|x| x == interaction);
+            let color = PALETTE[k.unwrap_or(0) % PALETTE.len()];
+            write!(
+                res,
+                "shape=circle, style=filled, width=0.15, label=\"\", color={}, tooltip=\"{}\"",
+                color,
+                escape(&interaction.to_string())
+            )
+            .unwrap();
+        }
+        writeln!(res, "];").unwrap();
+    }
+    for line in d.lines() {
+        writeln!(
+            res,
+            "{}{}{} -- {}{} [{}];",
+            indent,
+            prefix,
+            line.from,
+            prefix,
+            line.to,
+            style(&line, lagrangian)
+        )
+        .unwrap();
+    }
+    res
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
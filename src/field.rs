@@ -1,6 +1,11 @@
+use serde::{Deserialize, Serialize};
 use std::{fmt::Display, rc::Rc};
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+use crate::rational::Rational;
+
+/// `true` marks the barred field, e.g. `\bar\psi` is `Spinor(true)`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FieldKind {
     RealScalar,
     ComplexScalar(bool),
@@ -21,9 +26,17 @@ impl FieldKind {
             Spinor(t) => Spinor(!t),
         }
     }
+
+    /// In four dimensions.
+    pub fn mass_dimension(&self) -> Rational {
+        match self {
+            Spinor(_) => Rational::new(3, 2).unwrap(),
+            _ => Rational::ONE,
+        }
+    }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub struct Field {
     kind: FieldKind,
     name: Rc<str>,
@@ -52,11 +65,7 @@ impl Field {
 impl Display for Field {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.kind {
-            ComplexScalar(t) | ComplexVector(t) | Spinor(t) => {
-                if t {
-                    write!(f, "\\bar ")?;
-                }
-            }
+            ComplexScalar(true) | ComplexVector(true) | Spinor(true) => write!(f, "\\bar ")?,
             _ => (),
         }
         write!(f, "{}", self.name)
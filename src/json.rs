@@ -0,0 +1,133 @@
+use serde::{de::Error, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{diagram::*, lagrangian::*};
+
+/// Bumped whenever the layout below changes incompatibly; files of other versions are rejected
+/// rather than misread.
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Saved {
+    pub version: u32,
+    pub lagrangian: CheckedLagrangian,
+    pub diagrams: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub caption: String,
+    /// For other tools, ignored when loading
+    #[serde(skip_deserializing)]
+    pub metadata: Option<Metadata>,
+    pub diagram: Diagram,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Metadata {
+    pub loops: usize,
+    pub vertices: usize,
+    pub propagators: usize,
+    pub symmetry_factor: usize,
+    pub sign: isize,
+    pub fermion_loops: usize,
+    pub coupling_order: BTreeMap<String, usize>,
+}
+
+impl Metadata {
+    pub fn of(d: &Diagram) -> Self {
+        Metadata {
+            loops: d.loops(),
+            vertices: d.vertices(),
+            propagators: d.edges(),
+            symmetry_factor: d.symmetry_factor(),
+            sign: d.fermion_sign(),
+            fermion_loops: d.fermion_loops(),
+            coupling_order: d
+                .coupling_order()
+                .into_iter()
+                .map(|(g, n)| (g.to_string(), n))
+                .collect(),
+        }
+    }
+}
+
+pub fn write<'a>(
+    lagrangian: &CheckedLagrangian,
+    diagrams: impl IntoIterator<Item = (String, &'a Diagram)>,
+) -> String {
+    let saved = Saved {
+        version: VERSION,
+        lagrangian: lagrangian.clone(),
+        diagrams: diagrams
+            .into_iter()
+            .map(|(caption, d)| Entry {
+                caption,
+                metadata: Some(Metadata::of(d)),
+                diagram: d.clone(),
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&saved).unwrap() + "\n"
+}
+
+pub fn read(text: &str) -> Result<Saved, serde_json::Error> {
+    let saved: Saved = serde_json::from_str(text)?;
+    if saved.version != VERSION {
+        return Err(Error::custom(format!(
+            "unsupported version {}, expected {}",
+            saved.version, VERSION
+        )));
+    }
+    for (k, entry) in saved.diagrams.iter().enumerate() {
+        let unknown = entry
+            .diagram
+            .nodes()
+            .iter()
+            .filter_map(|u| u.interaction())
+            .any(|x| !saved.lagrangian.interactions().contains(x));
+        if unknown {
+            return Err(Error::custom(format!(
+                "diagram {} uses an interaction missing from the Lagrangian",
+                k + 1
+            )));
+        }
+    }
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    fn saved() -> String {
+        let (lagrangian, symbols) = parse_lagrangian(r"g\phi\bar\psi\psi").unwrap();
+        let process = parse_process(&lagrangian, &symbols, r"\phi -> \phi").unwrap();
+        let diagrams = Diagram::generate(&process, lagrangian.interactions(), 2, None);
+        let diagrams = diagrams.collect::<Vec<_>>();
+        assert!(!diagrams.is_empty());
+        write(
+            &lagrangian,
+            diagrams.iter().map(|d| (String::from("caption"), d)),
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = saved();
+        let saved = read(&text).unwrap();
+        let entries = saved
+            .diagrams
+            .iter()
+            .map(|x| (x.caption.clone(), &x.diagram));
+        assert_eq!(write(&saved.lagrangian, entries), text);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let text = saved().replacen("\"version\": 1", "\"version\": 2", 1);
+        let e = read(&text).err().unwrap();
+        assert!(e.to_string().starts_with("unsupported version 2"));
+    }
+}
@@ -1,21 +1,145 @@
 use itertools::Itertools;
-use std::fmt::Display;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, rc::Rc};
 
-use crate::field::*;
+use crate::{field::*, rational::Rational};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Charge {
+    Scalar,
+    Vector,
+    Fermion,
+}
+
+impl Display for Charge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Charge::Scalar => write!(f, "scalar charge"),
+            Charge::Vector => write!(f, "vector charge"),
+            Charge::Fermion => write!(f, "fermion number"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LagrangianError {
+    /// Fewer than three fields in an interaction
+    TooFewFields { term: Vec<Field> },
+    /// A term with fewer than three fields that is neither a kinetic nor a mass term
+    NotAPropagator { term: Vec<Field> },
+    /// A slashed derivative or a parenthesised operator in an interaction
+    OperatorCoupling { term: Vec<Field> },
+    /// A second kinetic or mass term for the same field
+    Redefined { field: Field },
+    /// A field without kinetic term, while other fields have one
+    NoPropagator { field: Field },
+    /// Each violated charge with the net charge of the term
+    Unbalanced {
+        term: Vec<Field>,
+        charges: Vec<(Charge, isize)>,
+    },
+    /// A field whose conjugate appears in no term
+    Unpaired { field: Field },
+    /// Each Lorentz index of an interaction that does not appear exactly twice, with its count
+    Uncontracted {
+        term: Vec<Field>,
+        indices: Vec<(String, usize)>,
+    },
+}
+
+impl Display for LagrangianError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LagrangianError::TooFewFields { term } => write!(
+                f,
+                "`{}` has only {} fields and is not an interaction",
+                term.iter().join(" "),
+                term.len()
+            ),
+            LagrangianError::NotAPropagator { term } => write!(
+                f,
+                "`{}` is neither a kinetic nor a mass term",
+                term.iter().join(" ")
+            ),
+            LagrangianError::OperatorCoupling { term } => write!(
+                f,
+                "`{}` is an interaction with a slashed derivative or a kinetic operator, which are \
+                 not supported",
+                term.iter().join(" ")
+            ),
+            LagrangianError::Redefined { field } => {
+                write!(f, "`{}` has more than one kinetic or mass term", field)
+            }
+            LagrangianError::NoPropagator { field } => {
+                write!(f, "`{}` has no kinetic term", field)
+            }
+            LagrangianError::Unbalanced { term, charges } => write!(
+                f,
+                "`{}` violates {}",
+                term.iter().join(" "),
+                charges
+                    .iter()
+                    .map(|(c, n)| format!("{} by {:+}", c, n))
+                    .join(" and ")
+            ),
+            LagrangianError::Unpaired { field } => write!(
+                f,
+                "`{}` appears but its conjugate `{}` does not",
+                field,
+                field.anti()
+            ),
+            LagrangianError::Uncontracted { term, indices } => write!(
+                f,
+                "`{}` {}",
+                term.iter().join(" "),
+                indices
+                    .iter()
+                    .map(|(i, n)| match n {
+                        1 => format!("leaves `{}` uncontracted", i),
+                        n => format!("uses `{}` {} times", i, n),
+                    })
+                    .join(" and ")
+            ),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(try_from = "RawInteraction")]
 pub struct Interaction {
     factors: Vec<Field>,
+    coupling: Option<Rc<str>>,
+    prefactor: Rational,
+    /// Position of the differentiated factor and the index of the derivative, sorted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    derivatives: Vec<(usize, Rc<str>)>,
+    /// Position of each vector factor written with a Lorentz index, and the index
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    indices: Vec<(usize, Rc<str>)>,
+    /// Number of factors before each gamma matrix, and its index, in the order written
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    gammas: Vec<(usize, Rc<str>)>,
+}
+
+/// Checked again when loading, as if it had just been parsed.
+#[derive(Deserialize)]
+struct RawInteraction {
+    factors: Vec<Field>,
+    coupling: Option<Rc<str>>,
+    prefactor: Rational,
+    #[serde(default)]
+    derivatives: Vec<(usize, Rc<str>)>,
+    #[serde(default)]
+    indices: Vec<(usize, Rc<str>)>,
+    #[serde(default)]
+    gammas: Vec<(usize, Rc<str>)>,
 }
 
 impl Interaction {
-    pub fn new(factors: impl Iterator<Item = Field>) -> Self {
+    pub fn try_new(factors: impl Iterator<Item = Field>) -> Result<Self, LagrangianError> {
         let factors: Vec<_> = factors.collect();
         if factors.len() < 3 {
-            panic!("存在非相互作用项（传播子）");
-        }
-        if factors.len() > 4 {
-            panic!("相互作用项的乘数不应超过四个");
+            return Err(LagrangianError::TooFewFields { term: factors });
         }
 
         let mut scalar_number = 0;
@@ -31,28 +155,297 @@ impl Interaction {
             }
         });
 
-        if scalar_number != 0 || vector_number != 0 || spinor_number != 0 {
-            panic!("相互作用项违反了守恒律");
+        let charges: Vec<_> = [
+            (Charge::Scalar, scalar_number),
+            (Charge::Vector, vector_number),
+            (Charge::Fermion, spinor_number),
+        ]
+        .into_iter()
+        .filter(|x| x.1 != 0)
+        .collect();
+        if !charges.is_empty() {
+            return Err(LagrangianError::Unbalanced {
+                term: factors,
+                charges,
+            });
         }
 
-        Self { factors }
+        Ok(Self {
+            factors,
+            coupling: None,
+            prefactor: Rational::ONE,
+            derivatives: vec![],
+            indices: vec![],
+            gammas: vec![],
+        })
+    }
+
+    pub fn with_coefficient(self, prefactor: Rational, coupling: Option<&str>) -> Self {
+        Self {
+            coupling: coupling.map(Rc::from),
+            prefactor,
+            ..self
+        }
+    }
+
+    /// Each pair is the position of a factor and the Lorentz index of one derivative acting on it.
+    pub fn with_derivatives(self, mut derivatives: Vec<(usize, Rc<str>)>) -> Self {
+        debug_assert!(derivatives.iter().all(|x| x.0 < self.factors.len()));
+        derivatives.sort();
+        Self {
+            derivatives,
+            ..self
+        }
+    }
+
+    /// Each pair is the number of factors before a gamma matrix and its Lorentz index.
+    pub fn with_gammas(self, gammas: Vec<(usize, Rc<str>)>) -> Self {
+        debug_assert!(gammas.iter().all(|x| x.0 <= self.factors.len()));
+        Self { gammas, ..self }
+    }
+
+    /// Fails unless every index, counting those of the derivatives and gamma matrices, appears
+    /// exactly twice.
+    pub fn with_indices(self, mut indices: Vec<(usize, Rc<str>)>) -> Result<Self, LagrangianError> {
+        debug_assert!(indices.iter().all(|x| x.0 < self.factors.len()));
+        indices.sort();
+        let wrong: Vec<_> = indices
+            .iter()
+            .chain(&self.derivatives)
+            .chain(&self.gammas)
+            .map(|x| x.1.to_string())
+            .counts()
+            .into_iter()
+            .filter(|x| x.1 != 2)
+            .sorted()
+            .collect();
+        if !wrong.is_empty() {
+            return Err(LagrangianError::Uncontracted {
+                term: self.factors,
+                indices: wrong,
+            });
+        }
+        Ok(Self { indices, ..self })
     }
 
     pub fn factors(&self) -> &[Field] {
         &self.factors
     }
+
+    /// Every factor conjugated, in reverse order so that fermions keep their order relative to
+    /// each other, and gamma matrices with them. Couplings are taken to be real.
+    pub fn conjugate(&self) -> Self {
+        let n = self.factors.len();
+        let gammas = self.gammas.iter().rev();
+        let flip = |x: &Vec<(usize, Rc<str>)>| {
+            x.iter()
+                .map(|(k, i)| (n - 1 - k, Rc::clone(i)))
+                .sorted()
+                .collect()
+        };
+        Self {
+            factors: self.factors.iter().rev().map(Field::anti).collect(),
+            coupling: self.coupling.clone(),
+            prefactor: self.prefactor,
+            derivatives: flip(&self.derivatives),
+            indices: flip(&self.indices),
+            gammas: gammas.map(|(k, i)| (n - k, Rc::clone(i))).collect(),
+        }
+    }
+
+    /// Whether the conjugate is the same term, up to the order of the factors.
+    pub fn is_hermitian(&self) -> bool {
+        type Factor<'a> = (&'a Field, Vec<&'a str>, Option<&'a str>, Vec<&'a str>);
+        fn canonical(x: &Interaction) -> Vec<Factor<'_>> {
+            (0..x.factors.len())
+                .map(|k| {
+                    let gammas = x.gammas(k).collect();
+                    (
+                        &x.factors[k],
+                        x.derivatives(k).collect(),
+                        x.index(k),
+                        gammas,
+                    )
+                })
+                .sorted()
+                .collect()
+        }
+        canonical(self) == canonical(&self.conjugate())
+    }
+
+    /// The Lorentz index of the vector factor at `k`, if it was written with one.
+    pub fn index(&self, k: usize) -> Option<&str> {
+        self.indices.iter().find(|x| x.0 == k).map(|x| x.1.as_ref())
+    }
+
+    /// Indices of the derivatives acting on the factor at `k`.
+    pub fn derivatives(&self, k: usize) -> impl Iterator<Item = &str> {
+        self.derivatives
+            .iter()
+            .filter(move |x| x.0 == k)
+            .map(|x| x.1.as_ref())
+    }
+
+    /// Indices of the gamma matrices written right before the factor at `k`.
+    pub fn gammas(&self, k: usize) -> impl Iterator<Item = &str> {
+        self.gammas
+            .iter()
+            .filter(move |x| x.0 == k)
+            .map(|x| x.1.as_ref())
+    }
+
+    /// Of the operator, so that the coupling has dimension four minus this.
+    pub fn mass_dimension(&self) -> Rational {
+        self.factors
+            .iter()
+            .map(|f| f.kind().mass_dimension())
+            .fold(Rational::integer(self.derivatives.len() as i64), |x, y| {
+                x + y
+            })
+    }
+
+    pub fn coupling(&self) -> Option<&str> {
+        self.coupling.as_deref()
+    }
+
+    /// The vertex factor divided by `i` and the coupling: the prefactor times the number of ways
+    /// to attach the legs to identical fields, so that `-\frac{\lambda}{4!}\phi^4` gives `-1`.
+    /// With derivatives each of those ways carries different momenta, which are left out.
+    pub fn vertex_factor(&self) -> Rational {
+        self.factors
+            .iter()
+            .counts()
+            .values()
+            .map(|&n| Rational::factorial(n as i64).unwrap())
+            .fold(self.prefactor, |x, y| x * y)
+    }
+}
+
+impl TryFrom<RawInteraction> for Interaction {
+    type Error = String;
+
+    fn try_from(raw: RawInteraction) -> Result<Self, String> {
+        let RawInteraction {
+            factors,
+            coupling,
+            prefactor,
+            derivatives,
+            indices,
+            gammas,
+        } = raw;
+        let n = factors.len();
+        let outside = derivatives.iter().chain(&indices).find(|x| x.0 >= n);
+        if let Some((k, _)) = outside.or(gammas.iter().find(|x| x.0 > n)) {
+            return Err(format!("index on factor {} of {}", k, n));
+        }
+        Interaction::try_new(factors.into_iter())
+            .and_then(|x| {
+                x.with_coefficient(prefactor, coupling.as_deref())
+                    .with_derivatives(derivatives)
+                    .with_gammas(gammas)
+                    .with_indices(indices)
+            })
+            .map_err(|e| e.to_string())
+    }
 }
 
 impl Display for Interaction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.factors.iter().join(" * "))
+        if self.prefactor != Rational::ONE {
+            write!(f, "{} * ", self.prefactor)?;
+        }
+        if let Some(g) = &self.coupling {
+            write!(f, "{} * ", g)?;
+        }
+        let factors = self.factors.iter().enumerate().map(|(k, x)| {
+            let field = match self.index(k) {
+                Some(i) => format!("{}_{}", x, i),
+                None => x.to_string(),
+            };
+            self.gammas(k)
+                .map(|i| format!("\\gamma^{} ", i))
+                .chain(self.derivatives(k).map(|i| format!("\\partial_{} ", i)))
+                .chain([field])
+                .join("")
+        });
+        write!(f, "{}", factors.format(" * "))?;
+        for i in self.gammas(self.factors.len()) {
+            write!(f, " \\gamma^{}", i)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kinetic {
+    /// `\partial_\mu\phi\partial^\mu\phi`, momentum squared in the propagator
+    KleinGordon,
+    /// `\bar\psi i\gamma^\mu\partial_\mu\psi`, slashed momentum in the propagator
+    Dirac,
+}
+
+impl Display for Kinetic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kinetic::KleinGordon => write!(f, "Klein-Gordon"),
+            Kinetic::Dirac => write!(f, "Dirac"),
+        }
+    }
+}
+
+/// The quadratic terms of one field and its conjugate.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Propagator {
+    /// The unbarred one of the pair
+    field: Field,
+    kinetic: Option<Kinetic>,
+    mass: Option<Rc<str>>,
+}
+
+impl Propagator {
+    pub fn try_new(
+        term: Vec<Field>,
+        kinetic: Option<Kinetic>,
+        mass: Option<&str>,
+    ) -> Result<Self, LagrangianError> {
+        match term.as_slice() {
+            [f, g] if f == &g.anti() && (kinetic.is_some() || mass.is_some()) => Ok(Propagator {
+                field: f.clone().min(g.clone()),
+                kinetic,
+                mass: mass.map(Rc::from),
+            }),
+            _ => Err(LagrangianError::NotAPropagator { term }),
+        }
+    }
+
+    pub fn field(&self) -> &Field {
+        &self.field
+    }
+
+    pub fn mass(&self) -> Option<&str> {
+        self.mass.as_deref()
+    }
+}
+
+impl Display for Propagator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kinetic {
+            Some(k) => write!(f, "{}", k)?,
+            None => write!(f, "no kinetic term")?,
+        }
+        match &self.mass {
+            Some(m) => write!(f, ", mass {}", m),
+            None => write!(f, ", massless"),
+        }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UncheckedLagrangian {
     fields: Vec<Field>,
     interactions: Vec<Interaction>,
+    propagators: Vec<Propagator>,
 }
 
 impl UncheckedLagrangian {
@@ -60,35 +453,92 @@ impl UncheckedLagrangian {
         UncheckedLagrangian {
             fields: vec![],
             interactions: vec![],
+            propagators: vec![],
+        }
+    }
+
+    fn add_field(&mut self, f: &Field) {
+        if !self.fields.iter().any(|x| x == f) {
+            self.fields.push(f.clone());
         }
     }
 
     pub fn push(&mut self, int: Interaction) {
         self.interactions.push(int.clone());
         for f in &int.factors {
-            if !self.fields.iter().any(|x| x == f) {
-                self.fields.push(f.clone());
-            }
+            self.add_field(f);
         }
     }
+
+    pub fn interactions(&self) -> &[Interaction] {
+        &self.interactions
+    }
+
+    /// Adds the conjugate of every interaction from `start` on that is not Hermitian.
+    pub fn push_conjugates(&mut self, start: usize) {
+        let conjugates = self.interactions[start..]
+            .iter()
+            .filter(|x| !x.is_hermitian())
+            .map(Interaction::conjugate)
+            .collect_vec();
+        for x in conjugates {
+            self.push(x);
+        }
+    }
+
+    /// Merges with an earlier kinetic or mass term of the same field.
+    pub fn push_propagator(&mut self, p: Propagator) -> Result<(), LagrangianError> {
+        self.add_field(&p.field);
+        self.add_field(&p.field.anti());
+        let Some(q) = self.propagators.iter_mut().find(|q| q.field == p.field) else {
+            self.propagators.push(p);
+            return Ok(());
+        };
+        if p.kinetic.is_some() && q.kinetic.is_some() || p.mass.is_some() && q.mass.is_some() {
+            return Err(LagrangianError::Redefined { field: p.field });
+        }
+        q.kinetic = q.kinetic.or(p.kinetic);
+        q.mass = q.mass.take().or(p.mass);
+        Ok(())
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedLagrangian", into = "UncheckedLagrangian")]
 pub struct CheckedLagrangian {
     inner: UncheckedLagrangian,
 }
 
 impl CheckedLagrangian {
-    pub fn new(inner: UncheckedLagrangian) -> Self {
-        for f in inner.fields.iter() {
-            inner
-                .fields
-                .iter()
-                .find(|&x| x == &f.anti())
-                .expect("存在未配对的场算符");
+    /// Fails with every field whose conjugate is missing, and, unless no kinetic terms are given
+    /// at all, every field without one.
+    pub fn try_new(inner: UncheckedLagrangian) -> Result<Self, Vec<LagrangianError>> {
+        let mut errors: Vec<_> = inner
+            .fields
+            .iter()
+            .filter(|f| !inner.fields.contains(&f.anti()))
+            .map(|f| LagrangianError::Unpaired { field: f.clone() })
+            .collect();
+        if inner.propagators.iter().any(|p| p.kinetic.is_some()) {
+            let kinetic = |f: &Field| {
+                inner
+                    .propagators
+                    .iter()
+                    .any(|p| p.kinetic.is_some() && (&p.field == f || p.field == f.anti()))
+            };
+            errors.extend(
+                inner
+                    .fields
+                    .iter()
+                    .filter(|f| f <= &&f.anti() && !kinetic(f))
+                    .map(|f| LagrangianError::NoPropagator { field: f.clone() }),
+            );
+        }
+        if !errors.is_empty() {
+            return Err(errors);
         }
 
-        CheckedLagrangian { inner }
+        Ok(CheckedLagrangian { inner })
     }
 
     pub fn fields(&self) -> &[Field] {
@@ -98,6 +548,35 @@ impl CheckedLagrangian {
     pub fn interactions(&self) -> &[Interaction] {
         &self.inner.interactions
     }
+
+    pub fn propagators(&self) -> &[Propagator] {
+        &self.inner.propagators
+    }
+
+    pub fn propagator(&self, field: &Field) -> Option<&Propagator> {
+        self.propagators()
+            .iter()
+            .find(|p| &p.field == field || p.field == field.anti())
+    }
+
+    /// `None` for massless fields, and for all fields if no masses are given.
+    pub fn mass(&self, field: &Field) -> Option<&str> {
+        self.propagator(field)?.mass()
+    }
+}
+
+impl TryFrom<UncheckedLagrangian> for CheckedLagrangian {
+    type Error = String;
+
+    fn try_from(inner: UncheckedLagrangian) -> Result<Self, String> {
+        CheckedLagrangian::try_new(inner).map_err(|e| e.iter().join("; "))
+    }
+}
+
+impl From<CheckedLagrangian> for UncheckedLagrangian {
+    fn from(lagrangian: CheckedLagrangian) -> Self {
+        lagrangian.inner
+    }
 }
 
 impl Display for CheckedLagrangian {
@@ -105,3 +584,145 @@ impl Display for CheckedLagrangian {
         write!(f, "{}", self.interactions().iter().join(" + "))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    fn interactions(text: &str) -> Vec<String> {
+        let (lagrangian, _) = parse_lagrangian(text).unwrap();
+        lagrangian
+            .interactions()
+            .iter()
+            .map(|x| x.to_string())
+            .collect()
+    }
+
+    fn interaction(text: &str) -> Interaction {
+        let (lagrangian, _) = parse_lagrangian(text).unwrap();
+        lagrangian.interactions()[0].clone()
+    }
+
+    #[test]
+    fn coefficients() {
+        let x = interaction(r"-\frac{\lambda}{4!}\phi^4");
+        assert_eq!(x.coupling(), Some(r"\lambda"));
+        assert_eq!(
+            x.to_string(),
+            r"-1/24 * \lambda * \phi * \phi * \phi * \phi"
+        );
+        assert_eq!(x.vertex_factor(), Rational::integer(-1));
+
+        let x = interaction(r"g\bar\psi\psi\phi");
+        assert_eq!(
+            (x.coupling(), x.vertex_factor()),
+            (Some("g"), Rational::ONE)
+        );
+
+        // 3/2 times the 3! ways to attach three legs
+        let x = interaction(r"\frac32 \phi^3");
+        assert_eq!(
+            (x.coupling(), x.vertex_factor()),
+            (None, Rational::integer(9))
+        );
+    }
+
+    #[test]
+    fn derivatives_add_to_mass_dimension() {
+        let x = interaction(r"e\varphi^* \partial_\mu \varphi A^\mu");
+        assert_eq!(x.derivatives(1).collect_vec(), [r"\mu"]);
+        assert_eq!(x.derivatives(0).count(), 0);
+        assert_eq!(x.mass_dimension(), Rational::integer(4));
+
+        let x = interaction(r"g\partial_\mu\phi\partial^\mu\phi\phi^2");
+        assert_eq!(x.mass_dimension(), Rational::integer(6));
+        assert_eq!(
+            interaction(r"g\bar\psi\psi\phi").mass_dimension(),
+            Rational::integer(4)
+        );
+    }
+
+    #[test]
+    fn lorentz_indices() {
+        let (lagrangian, _) =
+            parse_lagrangian(r"g A_\mu A^\mu \phi + h A_{\nu} A^{\nu}\phi^2").unwrap();
+        let vectors = lagrangian
+            .fields()
+            .iter()
+            .filter(|f| f.kind() == RealVector);
+        assert_eq!(vectors.count(), 1);
+        assert_eq!(lagrangian.interactions()[0].index(1), Some(r"\mu"));
+
+        let Err(ModelError::Lagrangian(errors)) = parse_lagrangian(r"g A_\mu A^\nu \phi") else {
+            panic!("accepted an uncontracted index");
+        };
+        let LagrangianError::Uncontracted { indices, .. } = &errors[0] else {
+            panic!("{} is not about indices", errors[0]);
+        };
+        assert_eq!(indices, &[(r"\mu".to_string(), 1), (r"\nu".to_string(), 1)]);
+    }
+
+    #[test]
+    fn gamma_matrices_carry_indices() {
+        let x = interaction(r"e\bar\psi\gamma^\mu\psi A_\mu + h.c.");
+        assert_eq!(x.to_string(), r"e * \bar \psi * \gamma^\mu \psi * A_\mu");
+        assert_eq!(x.gammas(1).collect_vec(), [r"\mu"]);
+        assert!(x.is_hermitian());
+
+        let Err(ModelError::Lagrangian(errors)) = parse_lagrangian(r"e\bar\psi\gamma^\mu\psi\phi")
+        else {
+            panic!("accepted an uncontracted gamma matrix");
+        };
+        assert!(matches!(errors[0], LagrangianError::Uncontracted { .. }));
+    }
+
+    #[test]
+    fn propagators() {
+        let (lagrangian, _) = parse_lagrangian(
+            r"\frac12\partial_\mu\phi\partial^\mu\phi - \frac12 M^2\phi^2
+            + \bar\psi(i\gamma^\mu\partial_\mu - m)\psi - g\phi\bar\psi\psi",
+        )
+        .unwrap();
+        let table = lagrangian
+            .propagators()
+            .iter()
+            .map(|p| format!("{}: {}", p.field(), p))
+            .collect_vec();
+        assert_eq!(
+            table,
+            [r"\phi: Klein-Gordon, mass M", r"\psi: Dirac, mass m"]
+        );
+        assert_eq!(lagrangian.interactions().len(), 1);
+
+        let Err(ModelError::Lagrangian(errors)) =
+            parse_lagrangian(r"\frac12\partial_\mu\phi\partial^\mu\phi + g\phi\bar\psi\psi")
+        else {
+            panic!("accepted a field without kinetic term");
+        };
+        let field = Field::new(Spinor(false), r"\psi");
+        assert_eq!(errors, [LagrangianError::NoPropagator { field }]);
+    }
+
+    #[test]
+    fn hermitian_conjugate_is_added_once() {
+        let model = r"field e, \nu: spinor; field h: real scalar;";
+        assert_eq!(
+            interactions(&format!(r"{} y\bar\nu e h + h.c.", model)),
+            [r"y * \bar \nu * e * h", r"y * h * \bar e * \nu"]
+        );
+        assert_eq!(
+            interactions(r"g\bar\psi\psi\phi + h.c."),
+            [r"g * \bar \psi * \psi * \phi"]
+        );
+    }
+
+    #[test]
+    fn conjugate_does_not_balance_charge() {
+        let Err(ModelError::Lagrangian(errors)) = parse_lagrangian(r"g\varphi F \phi + h.c.")
+        else {
+            panic!("accepted a term that violates charge");
+        };
+        assert!(matches!(errors[0], LagrangianError::Unbalanced { .. }));
+    }
+}
@@ -0,0 +1,313 @@
+use itertools::Itertools;
+use std::{
+    collections::{HashMap, VecDeque},
+    f64::consts::PI,
+};
+
+use crate::{diagram::*, field::*};
+
+pub type Point = (f64, f64);
+
+#[derive(Clone, Copy, Debug)]
+pub enum Shape {
+    Straight,
+    /// Control point offset, relative to the distance between the two ends
+    Bent(f64),
+    /// Direction of the loop as seen from its vertex
+    Loop(f64),
+}
+
+#[derive(Clone, Debug)]
+pub struct Edge {
+    pub line: Line,
+    pub shape: Shape,
+}
+
+/// Positions of all vertices of a diagram inside the unit square, incoming legs on the left and
+/// outgoing legs on the right.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub positions: Vec<Point>,
+    pub edges: Vec<Edge>,
+}
+
+pub fn layout(d: &Diagram) -> Layout {
+    let nodes = d.nodes();
+    let lines = d.lines();
+    let n = nodes.len();
+
+    let unknown = nodes
+        .iter()
+        .filter(|u| u.direction() == Some(Unknown))
+        .count();
+    let mut sides = vec![None; n];
+    let mut seen = 0;
+    for (i, u) in nodes.iter().enumerate() {
+        sides[i] = match u.direction() {
+            Some(In) => Some(0.0),
+            Some(Out) => Some(1.0),
+            Some(Unknown) => {
+                seen += 1;
+                Some(if seen * 2 <= unknown.max(1) { 0.0 } else { 1.0 })
+            }
+            None => None,
+        };
+    }
+
+    let mut positions = vec![(0.5, 0.5); n];
+    for side in [0.0, 1.0] {
+        let here: Vec<_> = (0..n).filter(|&i| sides[i] == Some(side)).collect();
+        for (k, &i) in here.iter().enumerate() {
+            positions[i] = (side, (k + 1) as f64 / (here.len() + 1) as f64);
+        }
+    }
+
+    let internal: Vec<_> = (0..n).filter(|&i| sides[i].is_none()).collect();
+    if internal.len() == n {
+        for (k, &i) in internal.iter().enumerate() {
+            let a = 2.0 * PI * k as f64 / n as f64;
+            positions[i] = (0.5 + 0.4 * a.cos(), 0.5 + 0.4 * a.sin());
+        }
+    } else {
+        let left = distances(n, &lines, |i| sides[i] == Some(0.0));
+        let right = distances(n, &lines, |i| sides[i] == Some(1.0));
+        for (k, &i) in internal.iter().enumerate() {
+            let x = match (left[i], right[i]) {
+                (Some(l), Some(r)) => l as f64 / (l + r) as f64,
+                (Some(_), None) => 0.75,
+                (None, Some(_)) => 0.25,
+                (None, None) => 0.5,
+            };
+            let y = (k + 1) as f64 / (internal.len() + 1) as f64;
+            positions[i] = (0.15 + 0.7 * x, y);
+        }
+    }
+
+    relax(&mut positions, &lines, &internal);
+    if internal.len() == n {
+        normalise(&mut positions);
+    }
+
+    Layout {
+        edges: shapes(&positions, lines),
+        positions,
+    }
+}
+
+fn distances(n: usize, lines: &[Line], start: impl Fn(usize) -> bool) -> Vec<Option<usize>> {
+    let mut dist = vec![None; n];
+    let mut queue = VecDeque::new();
+    for i in (0..n).filter(|&i| start(i)) {
+        dist[i] = Some(0);
+        queue.push_back(i);
+    }
+    while let Some(u) = queue.pop_front() {
+        for line in lines {
+            let v = if line.from == u {
+                line.to
+            } else if line.to == u {
+                line.from
+            } else {
+                continue;
+            };
+            if dist[v].is_none() {
+                dist[v] = Some(dist[u].unwrap() + 1);
+                queue.push_back(v);
+            }
+        }
+    }
+    dist
+}
+
+/// Fruchterman-Reingold, moving only the given vertices.
+fn relax(positions: &mut [Point], lines: &[Line], movable: &[usize]) {
+    const ROUNDS: usize = 300;
+    let n = positions.len();
+    let k = (1.0 / n as f64).sqrt() * 0.8;
+    for round in 0..ROUNDS {
+        let t = 0.1 * (1.0 - round as f64 / ROUNDS as f64);
+        let mut disp = vec![(0.0, 0.0); n];
+        for (a, b) in (0..n).tuple_combinations() {
+            let (dx, dy) = (
+                positions[a].0 - positions[b].0,
+                positions[a].1 - positions[b].1,
+            );
+            let dist = (dx * dx + dy * dy).sqrt().max(0.01);
+            let f = k * k / dist / dist;
+            disp[a].0 += dx * f;
+            disp[a].1 += dy * f;
+            disp[b].0 -= dx * f;
+            disp[b].1 -= dy * f;
+        }
+        for line in lines.iter().filter(|x| x.from != x.to) {
+            let (a, b) = (line.from, line.to);
+            let (dx, dy) = (
+                positions[a].0 - positions[b].0,
+                positions[a].1 - positions[b].1,
+            );
+            let dist = (dx * dx + dy * dy).sqrt().max(0.01);
+            let f = dist / k;
+            disp[a].0 -= dx * f;
+            disp[a].1 -= dy * f;
+            disp[b].0 += dx * f;
+            disp[b].1 += dy * f;
+        }
+        for &i in movable {
+            let (dx, dy) = disp[i];
+            let len = (dx * dx + dy * dy).sqrt().max(1e-9);
+            let step = len.min(t);
+            let (x, y) = positions[i];
+            positions[i] = (
+                (x + dx / len * step).clamp(0.1, 0.9),
+                (y + dy / len * step).clamp(0.05, 0.95),
+            );
+        }
+    }
+}
+
+fn normalise(positions: &mut [Point]) {
+    let (x0, x1) = positions
+        .iter()
+        .map(|p| p.0)
+        .minmax()
+        .into_option()
+        .unwrap();
+    let (y0, y1) = positions
+        .iter()
+        .map(|p| p.1)
+        .minmax()
+        .into_option()
+        .unwrap();
+    for p in positions.iter_mut() {
+        p.0 = 0.2 + 0.6 * (p.0 - x0) / (x1 - x0).max(1e-9);
+        p.1 = 0.2 + 0.6 * (p.1 - y0) / (y1 - y0).max(1e-9);
+    }
+}
+
+fn shapes(positions: &[Point], lines: Vec<Line>) -> Vec<Edge> {
+    let parallel = lines.iter().map(|x| (x.from, x.to)).counts();
+    let mut seen: HashMap<_, usize> = HashMap::new();
+    lines
+        .into_iter()
+        .map(|line| {
+            let m = parallel[&(line.from, line.to)];
+            let k = seen.entry((line.from, line.to)).or_default();
+            let shape = if line.from == line.to {
+                // point the loop away from the rest of the diagram
+                let p = positions[line.from];
+                let (cx, cy) = positions
+                    .iter()
+                    .fold((0.0, 0.0), |s, q| (s.0 + q.0 - p.0, s.1 + q.1 - p.1));
+                let base = if cx == 0.0 && cy == 0.0 {
+                    -PI / 2.0
+                } else {
+                    (-cy).atan2(-cx)
+                };
+                Shape::Loop(base + 0.9 * (2.0 * *k as f64 - (m as f64 - 1.0)))
+            } else if m > 1 {
+                Shape::Bent(0.3 * (2.0 * *k as f64 - (m as f64 - 1.0)) / (m as f64 - 1.0))
+            } else {
+                Shape::Straight
+            };
+            *k += 1;
+            Edge { line, shape }
+        })
+        .collect()
+}
+
+/// Points along an edge from `a` to `b`; `size` is the diameter of loops in the same units.
+pub fn sample(shape: Shape, a: Point, b: Point, size: f64, n: usize) -> Vec<Point> {
+    (0..=n)
+        .map(|k| {
+            let t = k as f64 / n as f64;
+            match shape {
+                Shape::Straight => (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t),
+                Shape::Bent(h) => {
+                    let c = (
+                        (a.0 + b.0) / 2.0 - (b.1 - a.1) * h,
+                        (a.1 + b.1) / 2.0 + (b.0 - a.0) * h,
+                    );
+                    let s = 1.0 - t;
+                    (
+                        s * s * a.0 + 2.0 * s * t * c.0 + t * t * b.0,
+                        s * s * a.1 + 2.0 * s * t * c.1 + t * t * b.1,
+                    )
+                }
+                Shape::Loop(dir) => {
+                    let r = size / 2.0;
+                    let c = (a.0 + r * dir.cos(), a.1 + r * dir.sin());
+                    let phi = dir + PI + 2.0 * PI * t;
+                    (c.0 + r * phi.cos(), c.1 + r * phi.sin())
+                }
+            }
+        })
+        .collect()
+}
+
+/// Plain-text rendering of a field name, e.g. `\bar \psi` becomes `ψ̄`.
+pub fn label(field: &Field) -> String {
+    plain(&field.to_string())
+}
+
+/// Plain-text rendering of a TeX name such as a mass or coupling.
+pub fn plain(text: &str) -> String {
+    let mut res = String::new();
+    let mut bar = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let piece = match c {
+            '\\' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphabetic() {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                if name == "bar" {
+                    bar = true;
+                    continue;
+                }
+                greek(&name).map_or(name, String::from)
+            }
+            ' ' | '{' | '}' => continue,
+            c => c.to_string(),
+        };
+        res.push_str(&piece);
+        if bar {
+            res.push('\u{304}');
+            bar = false;
+        }
+    }
+    res
+}
+
+fn greek(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "rho" => "ρ",
+        "sigma" => "σ",
+        "tau" => "τ",
+        "phi" => "φ",
+        "varphi" => "ϕ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "pm" => "±",
+        _ => return None,
+    })
+}
@@ -1,48 +1,190 @@
+mod ascii;
 mod cli;
 mod diagram;
+mod dot;
 mod field;
+mod json;
 mod lagrangian;
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+mod layout;
+mod parser;
+mod pdf;
+mod rational;
+mod svg;
+mod tikz;
 
-fn main() {
-    let (lagrangian, n) = cli::parse();
+use std::{
+    fs::{self, File},
+    io::{self, stdout, BufWriter, Write},
+};
 
-    println!("Lagrangian is {:#?}", lagrangian);
-    println!("Lagrangian is {}", lagrangian);
-    println!(
-        "Number of kinds of particles is {}",
-        lagrangian.fields().len()
+use cli::Format;
+use diagram::Diagram;
+use lagrangian::Interaction;
+use rational::Rational;
+
+fn main() -> std::io::Result<()> {
+    let cli::Config {
+        lagrangian,
+        process,
+        orders,
+        loops,
+        dedup,
+        filters,
+        limit,
+        format,
+        output,
+        pdf,
+        lualatex,
+        saved,
+    } = cli::parse();
+
+    let mut out: Box<dyn Write> = match (&output, format) {
+        (_, Format::SvgDir) => Box::new(io::sink()),
+        (Some(path), _) => Box::new(BufWriter::new(File::create(path)?)),
+        (None, _) => Box::new(stdout().lock()),
+    };
+
+    if format == Format::Text && saved.is_none() {
+        writeln!(out, "Lagrangian is {:#?}", lagrangian)?;
+        writeln!(out, "Lagrangian is {}", lagrangian)?;
+        writeln!(
+            out,
+            "Number of kinds of particles is {}",
+            lagrangian.fields().len()
+        )?;
+        for p in lagrangian.propagators() {
+            writeln!(out, "Propagator of {} is {}", p.field(), p)?;
+        }
+        for x in lagrangian.interactions() {
+            writeln!(out, "Vertex factor of {} is {}", x, vertex_factor(x))?;
+            let dimension = x.mass_dimension();
+            if dimension > Rational::integer(4) {
+                writeln!(
+                    out,
+                    "Mass dimension of {} is {}, so it is not renormalisable",
+                    x, dimension
+                )?;
+            } else {
+                writeln!(out, "Mass dimension of {} is {}", x, dimension)?;
+            }
+        }
+        writeln!(out, "Orders are {:?}", orders)?;
+        if let Some(l) = loops {
+            writeln!(out, "Number of loops is {}", l)?;
+        }
+    }
+
+    let mut collected = vec![];
+    for entry in saved.into_iter().flatten() {
+        if format == Format::Text {
+            writeln!(out, "Diagram ({}) is {}", entry.caption, entry.diagram)?;
+        }
+        collected.push((entry.caption, entry.diagram));
+    }
+    for order in orders {
+        let diag = Diagram::generate(
+            &process,
+            lagrangian.interactions(),
+            order as usize,
+            loops.map(|l| l as usize),
+        );
+        let diag = diag
+            .filter(|x| filters.accepts(x))
+            .take(limit.unwrap_or(usize::MAX));
+        let diag: Box<dyn Iterator<Item = (Diagram, String)>> = if dedup {
+            Box::new(Diagram::dedup(diag).into_iter().map(|x| {
+                let text = format!("{} contractions, {}", x.contractions(), describe(&x));
+                (x, text)
+            }))
+        } else {
+            Box::new(diag.map(|x| {
+                let text = describe(&x);
+                (x, text)
+            }))
+        };
+
+        let mut count = 0;
+        for (x, text) in diag {
+            count += 1;
+            if format == Format::Text {
+                writeln!(out, "Diagram ({}) is {}", text, x)?;
+            }
+            if format != Format::Text || pdf.is_some() {
+                collected.push((format!("Order {}, {}", order, text), x));
+            }
+        }
+        if format == Format::Text {
+            writeln!(out, "Order {}: {} diagrams", order, count)?;
+        }
+    }
+
+    let entries = || collected.iter().map(|(text, x)| (text.clone(), x));
+    let document =
+        (format == Format::Tikz || pdf.is_some()).then(|| tikz::document(entries(), &lagrangian));
+    match format {
+        Format::Text => (),
+        Format::Ascii => out.write_all(ascii::listing(entries(), &lagrangian).as_bytes())?,
+        Format::Json => out.write_all(json::write(&lagrangian, entries()).as_bytes())?,
+        Format::Tikz => out.write_all(document.as_ref().unwrap().text.as_bytes())?,
+        Format::Dot => out.write_all(dot::graphs(entries(), &lagrangian).as_bytes())?,
+        Format::DotClusters => out.write_all(dot::clusters(entries(), &lagrangian).as_bytes())?,
+        Format::Svg => out.write_all(svg::sheet(entries(), &lagrangian).as_bytes())?,
+        Format::SvgDir => {
+            let dir = output.unwrap();
+            fs::create_dir_all(&dir)?;
+            for (k, (_, x)) in collected.iter().enumerate() {
+                fs::write(
+                    dir.join(format!("diagram-{}.svg", k + 1)),
+                    svg::diagram(x, &lagrangian),
+                )?;
+            }
+        }
+    }
+    out.flush()?;
+
+    if let (Some(path), Some(document)) = (pdf, document) {
+        if let Err(e) = pdf::build(&document, &lualatex, &path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn describe(x: &Diagram) -> String {
+    let mut res = format!(
+        "{} loops, {} vertices, {} propagators, symmetry factor {}, sign {:+}, {} fermion loops",
+        x.loops(),
+        x.vertices(),
+        x.edges(),
+        x.symmetry_factor(),
+        x.fermion_sign(),
+        x.fermion_loops()
     );
-    println!("Maximum order is {}", n);
-
-    let diag = diagram::Diagram::new(vec![
-        diagram::Vertex::external(lagrangian.fields()[1].clone()),
-        diagram::Vertex::external(lagrangian.fields()[2].clone()),
-        diagram::Vertex::external(lagrangian.fields()[1].clone()),
-        diagram::Vertex::external(lagrangian.fields()[2].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[0].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[0].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[0].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[0].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[0].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[0].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[1].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[1].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[1].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[1].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[1].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[1].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[2].clone()),
-        diagram::Vertex::internal(lagrangian.interactions()[2].clone()),
-    ]);
-
-    println!("Diagram is {:#?}", diag);
-
-    let mut diag = diag.draw();
-    diag.shuffle(&mut thread_rng());
-
-    for x in &diag {
-        println!("Diagram is {}", x);
+    let couplings = x.coupling_order();
+    if !couplings.is_empty() {
+        let order = couplings.iter().map(|&(g, n)| match n {
+            1 => g.to_string(),
+            n => format!("{}^{}", g, n),
+        });
+        res += &format!(", coupling order {}", order.collect::<Vec<_>>().join(" "));
+    }
+    res
+}
+
+fn vertex_factor(x: &Interaction) -> String {
+    let mut res = match x.vertex_factor() {
+        v if v == Rational::ONE => String::from("i"),
+        v if v == Rational::integer(-1) => String::from("-i"),
+        v => format!("{} i", v),
+    };
+    if let Some(g) = x.coupling() {
+        res += &format!(" {}", g);
+    }
+    for k in 0..=x.factors().len() {
+        for i in x.gammas(k) {
+            res += &format!(" \\gamma^{}", i);
+        }
     }
+    res
 }
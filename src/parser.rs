@@ -0,0 +1,999 @@
+use itertools::Itertools;
+use std::{fmt::Display, rc::Rc};
+
+use crate::{diagram::*, field::*, lagrangian::*, rational::Rational};
+
+/// Kinds that can be declared, e.g. `field h: real scalar;`.
+const KINDS: [(&str, FieldKind); 5] = [
+    ("real scalar", RealScalar),
+    ("complex scalar", ComplexScalar(false)),
+    ("real vector", RealVector),
+    ("complex vector", ComplexVector(false)),
+    ("spinor", Spinor(false)),
+];
+
+/// Tokens that name a field, and the kind of the unbarred field each one names.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Symbols {
+    table: Vec<(String, FieldKind)>,
+    /// Whether the model declared them, so that other symbols after a field are errors
+    declared: bool,
+}
+
+impl Default for Symbols {
+    /// The symbols understood when the model declares none.
+    fn default() -> Self {
+        let table = [
+            ("\\phi", RealScalar),
+            ("\\varphi", ComplexScalar(false)),
+            ("A", RealVector),
+            ("F", ComplexVector(false)),
+            ("\\psi", Spinor(false)),
+        ];
+        Symbols {
+            table: table.map(|(t, k)| (t.to_string(), k)).to_vec(),
+            declared: false,
+        }
+    }
+}
+
+impl Symbols {
+    fn kind(&self, token: &str, bar: bool) -> Option<FieldKind> {
+        let (_, kind) = self.table.iter().find(|x| x.0 == token)?;
+        Some(if bar { kind.anti() } else { *kind })
+    }
+
+    /// Whether a field name starts with the token, by itself or before a superscript such as
+    /// the `W` of `W^\pm`.
+    fn starts_field(&self, token: &str) -> bool {
+        self.table.iter().any(|x| match x.0.strip_prefix(token) {
+            Some(rest) => rest.is_empty() || rest.starts_with('^'),
+            None => false,
+        })
+    }
+
+    fn expected(&self) -> Vec<String> {
+        self.table.iter().map(|x| format!("`{}`", x.0)).collect()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    /// Byte offset into the parsed text
+    pub offset: usize,
+    /// `None` at the end of the input
+    pub token: Option<String>,
+    pub expected: Vec<String>,
+}
+
+impl ParseError {
+    /// The message followed by the offending line with a caret under the token, and the line
+    /// number in front if the input has several lines.
+    pub fn render(&self, input: &str) -> String {
+        let start = input[..self.offset].rfind('\n').map_or(0, |x| x + 1);
+        let end = input[self.offset..]
+            .find('\n')
+            .map_or(input.len(), |x| self.offset + x);
+        let column = input[start..self.offset].chars().count();
+        let width = self.token.as_ref().map_or(1, |x| x.chars().count().max(1));
+        let gutter = if input.trim_end().contains('\n') {
+            format!("{} | ", input[..start].matches('\n').count() + 1)
+        } else {
+            String::new()
+        };
+        format!(
+            "{}\n{}{}\n{}{}",
+            self,
+            gutter,
+            &input[start..end],
+            " ".repeat(gutter.len() + column),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.token {
+            Some(t) => write!(f, "unexpected `{}` at byte {}", t, self.offset)?,
+            None => write!(f, "unexpected end of input at byte {}", self.offset)?,
+        }
+        match self.expected.as_slice() {
+            [] => Ok(()),
+            [x] => write!(f, ", expected {}", x),
+            xs => write!(f, ", expected one of {}", xs.iter().join(", ")),
+        }
+    }
+}
+
+/// Either the text does not parse, or it parses into terms that do not make a model.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ModelError {
+    Parse(ParseError),
+    Lagrangian(Vec<LagrangianError>),
+}
+
+impl From<ParseError> for ModelError {
+    fn from(e: ParseError) -> Self {
+        ModelError::Parse(e)
+    }
+}
+
+impl ModelError {
+    pub fn render(&self, input: &str) -> String {
+        match self {
+            ModelError::Parse(e) => e.render(input),
+            ModelError::Lagrangian(_) => self.to_string(),
+        }
+    }
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::Parse(e) => write!(f, "{}", e),
+            ModelError::Lagrangian(errors) => {
+                write!(f, "invalid Lagrangian:")?;
+                for e in errors {
+                    write!(f, "\n\t{}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// `\mu` and `{\mu}` are the same index.
+fn lorentz(block: String) -> String {
+    block
+        .trim_matches(|c| c == '{' || c == '}')
+        .trim()
+        .to_string()
+}
+
+fn quoted(tokens: &[&str]) -> Vec<String> {
+    tokens.iter().map(|x| format!("`{}`", x)).collect()
+}
+
+/// Commands that are never read as couplings.
+const RESERVED: [&str; 6] = [
+    "\\bar",
+    "\\frac",
+    "\\partial",
+    "\\gamma",
+    "\\slashed",
+    "\\not",
+];
+
+/// A number with the couplings multiplying it, each with its offset and power.
+type Coefficient = (Rational, Vec<(usize, String, u32)>);
+
+/// Each with its index, if any.
+enum Operator {
+    Partial(Option<String>),
+    Gamma(Option<String>),
+    /// `\slashed\partial` or `\not\partial`
+    Slashed,
+}
+
+struct Cursor<'a> {
+    text: &'a str,
+    pos: usize,
+    end: usize,
+    symbols: &'a Symbols,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str, range: std::ops::Range<usize>, symbols: &'a Symbols) -> Self {
+        Cursor {
+            text,
+            pos: range.start,
+            end: range.end,
+            symbols,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..self.end]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Also drops comments, from `%` to the end of the line.
+    fn drop_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some('%') => while self.bump().is_some_and(|c| c != '\n') {},
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// A command such as `\psi` or `\{`, or a single character.
+    fn token(&mut self) -> Option<&'a str> {
+        let start = self.pos;
+        if self.bump()? == '\\' {
+            if self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    self.bump();
+                }
+            } else {
+                self.bump();
+            }
+        }
+        Some(&self.text[start..self.pos])
+    }
+
+    fn error(&self, offset: usize, expected: Vec<String>) -> ParseError {
+        let mut at = Cursor::new(self.text, offset..self.end, self.symbols);
+        let token = if at.peek().is_some_and(|c| c.is_ascii_digit()) {
+            let digits = at.rest().find(|c: char| !c.is_ascii_digit());
+            Some(&at.rest()[..digits.unwrap_or(at.rest().len())])
+        } else {
+            at.token()
+        };
+        ParseError {
+            offset,
+            token: token.map(String::from),
+            expected,
+        }
+    }
+
+    fn block(&mut self, what: &str) -> Result<String, ParseError> {
+        self.drop_whitespace();
+        let start = self.pos;
+        match self.peek() {
+            None | Some('}') => Err(self.error(start, vec![what.to_string()])),
+            Some('{') => {
+                let mut layer = 0;
+                while let Some(c) = self.bump() {
+                    match c {
+                        '{' => layer += 1,
+                        '}' => layer -= 1,
+                        _ => (),
+                    }
+                    if layer == 0 {
+                        return Ok(self.text[start..self.pos].to_string());
+                    }
+                }
+                Err(self.error(self.pos, quoted(&["}"])))
+            }
+            Some(_) => Ok(self.token().unwrap().to_string()),
+        }
+    }
+
+    /// The `+` or `-` before the next term, or `None` at the end of the input. Anything else is
+    /// an error that also lists `expected`.
+    fn separator(&mut self, mut expected: Vec<String>) -> Result<Option<Rational>, ParseError> {
+        self.drop_whitespace();
+        match self.peek() {
+            None => Ok(None),
+            Some('+') => {
+                self.bump();
+                Ok(Some(Rational::ONE))
+            }
+            Some('-') => {
+                self.bump();
+                Ok(Some(Rational::integer(-1)))
+            }
+            Some(_) => {
+                expected.extend(quoted(&["+", "-"]));
+                expected.push(String::from("end of input"));
+                Err(self.error(self.pos, expected))
+            }
+        }
+    }
+
+    /// `field e, \mu: spinor;` and so on, each name a single token with an optional superscript
+    /// such as `W^\pm`. `None` if the text does not start with a declaration.
+    fn declarations(&mut self) -> Result<Option<Symbols>, ParseError> {
+        let mut table: Vec<(String, FieldKind)> = vec![];
+        loop {
+            self.drop_whitespace();
+            let keyword = self.rest().strip_prefix("field");
+            if !keyword.is_some_and(|x| x.starts_with(char::is_whitespace)) {
+                break;
+            }
+            self.pos += "field".len();
+            let mut names: Vec<String> = vec![];
+            loop {
+                self.drop_whitespace();
+                let at = self.pos;
+                let token = self.token().filter(|&t| {
+                    let word = t.strip_prefix('\\').unwrap_or(t);
+                    word.chars().all(|c| c.is_ascii_alphabetic())
+                        && !["i", "\\bar"].contains(&t)
+                        && !RESERVED.contains(&t)
+                });
+                let Some(token) = token else {
+                    return Err(self.error(at, vec![String::from("a new field name")]));
+                };
+                let mut name = token.to_string();
+                self.drop_whitespace();
+                if self.peek() == Some('^') {
+                    self.bump();
+                    self.drop_whitespace();
+                    let at = self.pos;
+                    let suffix = lorentz(self.block("a superscript")?);
+                    if suffix == "*" || suffix.parse::<u8>().is_ok() {
+                        return Err(self.error(
+                            at,
+                            vec![String::from("a superscript other than a power or `*`")],
+                        ));
+                    }
+                    name = format!("{}^{}", name, suffix);
+                }
+                if table.iter().any(|x| x.0 == name) || names.contains(&name) {
+                    return Err(self.error(at, vec![String::from("a new field name")]));
+                }
+                names.push(name);
+                self.drop_whitespace();
+                let at = self.pos;
+                match self.bump() {
+                    Some(',') => (),
+                    Some(':') => break,
+                    _ => return Err(self.error(at, quoted(&[",", ":"]))),
+                }
+            }
+            self.drop_whitespace();
+            let at = self.pos;
+            let mut words = vec![];
+            loop {
+                self.drop_whitespace();
+                let rest = self.rest();
+                let len = rest.find(|c: char| !c.is_ascii_alphabetic());
+                let word = &rest[..len.unwrap_or(rest.len())];
+                if word.is_empty() {
+                    break;
+                }
+                words.push(word);
+                self.pos += word.len();
+            }
+            let words = words.join(" ");
+            let Some(&(_, kind)) = KINDS.iter().find(|x| x.0 == words) else {
+                let mut error = self.error(at, KINDS.iter().map(|x| x.0.to_string()).collect());
+                if !words.is_empty() {
+                    error.token = Some(words);
+                }
+                return Err(error);
+            };
+            self.drop_whitespace();
+            let at = self.pos;
+            if self.bump() != Some(';') {
+                return Err(self.error(at, quoted(&[";"])));
+            }
+            table.extend(names.into_iter().map(|x| (x, kind)));
+        }
+        Ok((!table.is_empty()).then_some(Symbols {
+            table,
+            declared: true,
+        }))
+    }
+
+    /// A field with its power and, for vectors, its Lorentz index, or `None` if the next token
+    /// cannot start one. A superscript `*` conjugates like `\bar`, and a superscript that
+    /// completes a declared name such as `W^\pm` is part of the field.
+    fn field(&mut self) -> Result<Option<(Field, u8, Option<String>)>, ParseError> {
+        self.drop_whitespace();
+        let start = self.pos;
+        let token = match self.token() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let (token, mut bar) = if token == "\\bar" {
+            self.drop_whitespace();
+            let at = self.pos;
+            match self.token() {
+                Some(t) if self.symbols.starts_field(t) => (t, true),
+                _ => return Err(self.error(at, self.symbols.expected())),
+            }
+        } else {
+            (token, false)
+        };
+        if !self.symbols.starts_field(token) {
+            self.pos = start;
+            return Ok(None);
+        }
+
+        let mut subscript = None;
+        let mut superscript = None;
+        let mut at = self.pos;
+        loop {
+            self.drop_whitespace();
+            match self.peek() {
+                Some('_') if subscript.is_none() => {
+                    self.bump();
+                    subscript = Some(self.block("a subscript")?);
+                }
+                Some('^') if superscript.is_none() => {
+                    self.bump();
+                    self.drop_whitespace();
+                    at = self.pos;
+                    superscript = Some(self.block("a superscript")?);
+                }
+                _ => break,
+            }
+        }
+        let mut name = None;
+        if let Some(sup) = superscript.clone().map(lorentz) {
+            let (sup, star) = match sup.strip_suffix('*') {
+                Some(x) => (x.trim(), true),
+                None => (sup.as_str(), false),
+            };
+            let suffixed = format!("{}^{}", token, sup);
+            if self.symbols.kind(&suffixed, false).is_some() {
+                name = Some(suffixed);
+                superscript = None;
+                bar |= star;
+            }
+        }
+        let token = match name {
+            Some(x) => x,
+            None if self.symbols.kind(token, false).is_none() => {
+                let names = self.symbols.table.iter().map(|x| &x.0);
+                let expected = names.filter(|x| x.starts_with(&format!("{}^", token)));
+                return Err(self.error(start, expected.map(|x| format!("`{}`", x)).collect()));
+            }
+            None => token.to_string(),
+        };
+        if !bar && superscript.as_deref() == Some("*") {
+            bar = true;
+            superscript = None;
+        }
+        let kind = self.symbols.kind(&token, bar).unwrap();
+        let power = superscript.as_deref().unwrap_or("1");
+        let num: u8 = if let Ok(v) = power.trim_matches(|c| c == '{' || c == '}').trim().parse() {
+            superscript = None;
+            v
+        } else {
+            1
+        };
+
+        if matches!(kind, RealVector | ComplexVector(_)) {
+            let index = match (subscript, superscript) {
+                (Some(_), Some(_)) => {
+                    return Err(self.error(at, vec![String::from("a power")]));
+                }
+                (index, None) | (None, index) => index.map(lorentz),
+            };
+            return Ok(Some((Field::new(kind, &token), num, index)));
+        }
+        let mut name = token;
+        if let Some(sub) = subscript {
+            name.push('_');
+            name.push_str(&sub);
+        }
+        if let Some(sup) = superscript {
+            name.push('^');
+            name.push_str(&sup);
+        }
+        Ok(Some((Field::new(kind, &name), num, None)))
+    }
+
+    /// A number such as `4` or `4!`, a coupling such as `\lambda` or `g_s`, or a `\frac` of
+    /// those, with the offset of each coupling. `None` if the next token cannot start one.
+    fn coefficient(&mut self) -> Result<Option<Coefficient>, ParseError> {
+        self.drop_whitespace();
+        let start = self.pos;
+        if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.bump();
+            }
+            let too_large = self.error(start, vec![String::from("a smaller number")]);
+            let n: i64 = self.text[start..self.pos]
+                .parse()
+                .map_err(|_| too_large.clone())?;
+            if self.peek() == Some('!') {
+                self.bump();
+                let x = Rational::factorial(n).ok_or(too_large)?;
+                return Ok(Some((x, vec![])));
+            }
+            return Ok(Some((Rational::integer(n), vec![])));
+        }
+
+        let token = match self.token() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        if token == "i" {
+            // the imaginary unit, as in the Dirac term
+            return Ok(Some((Rational::ONE, vec![])));
+        }
+        if token == "\\frac" {
+            let (numerator, couplings) = self.fraction_part(false)?;
+            let at = self.pos;
+            let (denominator, _) = self.fraction_part(true)?;
+            let Some(recip) = denominator.recip() else {
+                return Err(self.error(at, vec![String::from("a nonzero denominator")]));
+            };
+            return Ok(Some((numerator * recip, couplings)));
+        }
+        let coupling = match token.strip_prefix('\\') {
+            Some(name) => name.chars().all(|c| c.is_ascii_alphabetic()) && name != "bar",
+            None => token.chars().all(|c| c.is_ascii_alphabetic()),
+        };
+        if !coupling || self.symbols.starts_field(token) || RESERVED.contains(&token) {
+            self.pos = start;
+            return Ok(None);
+        }
+        let mut name = token.to_string();
+        self.drop_whitespace();
+        if self.peek() == Some('_') {
+            self.bump();
+            name.push('_');
+            name.push_str(&self.block("a subscript")?);
+        }
+        self.drop_whitespace();
+        let mut power = 1;
+        if self.peek() == Some('^') {
+            self.bump();
+            self.drop_whitespace();
+            let at = self.pos;
+            let block = self.block("a power")?;
+            power = block
+                .trim_matches(|c| c == '{' || c == '}')
+                .parse()
+                .map_err(|_| self.error(at, vec![String::from("a numeric power")]))?;
+        }
+        Ok(Some((Rational::ONE, vec![(start, name, power)])))
+    }
+
+    /// `\partial_\mu`, `\gamma^\mu`, `\slashed\partial` or `\not\partial`, with the index of a
+    /// derivative or gamma matrix. `None` if the next token is none of them.
+    fn operator(&mut self) -> Result<Option<Operator>, ParseError> {
+        self.drop_whitespace();
+        let start = self.pos;
+        let op = match self.token() {
+            Some("\\partial") => Operator::Partial(None),
+            Some("\\gamma") => Operator::Gamma(None),
+            Some("\\slashed") | Some("\\not") => {
+                self.drop_whitespace();
+                let at = self.pos;
+                let block = self.block("`\\partial`")?;
+                if block.trim_matches(|c| c == '{' || c == '}').trim() != "\\partial" {
+                    return Err(self.error(at, quoted(&["\\partial"])));
+                }
+                return Ok(Some(Operator::Slashed));
+            }
+            _ => {
+                self.pos = start;
+                return Ok(None);
+            }
+        };
+        self.drop_whitespace();
+        if !matches!(self.peek(), Some('_') | Some('^')) {
+            return Ok(Some(op));
+        }
+        self.bump();
+        let index = lorentz(self.block("an index")?);
+        Ok(Some(match op {
+            Operator::Partial(_) => Operator::Partial(Some(index)),
+            Operator::Gamma(_) => Operator::Gamma(Some(index)),
+            op => op,
+        }))
+    }
+
+    /// A parenthesised operator between two fields, such as `(i\gamma^\mu\partial_\mu - m)`.
+    fn group(&mut self) -> Result<(Option<Kinetic>, Option<String>), ParseError> {
+        self.bump();
+        let mut kinetic = None;
+        let mut mass = None;
+        loop {
+            let (mut partials, mut gammas, mut couplings) = (0, 0, vec![]);
+            self.drop_whitespace();
+            let start = self.pos;
+            loop {
+                if let Some(op) = self.operator()? {
+                    match op {
+                        Operator::Partial(_) => partials += 1,
+                        Operator::Gamma(_) => gammas += 1,
+                        Operator::Slashed => (partials, gammas) = (partials + 1, gammas + 1),
+                    }
+                } else if let Some((_, cs)) = self.coefficient()? {
+                    couplings.extend(cs);
+                } else {
+                    break;
+                }
+            }
+            match (partials, gammas, couplings.as_slice()) {
+                (1, 1, _) => kinetic = Some(Kinetic::Dirac),
+                (2, 0, _) => kinetic = Some(Kinetic::KleinGordon),
+                (0, 0, [(_, m, _)]) => mass = Some(m.clone()),
+                _ => {
+                    let expected = vec![String::from("a kinetic operator or a mass")];
+                    return Err(self.error(start, expected));
+                }
+            }
+            self.drop_whitespace();
+            let at = self.pos;
+            match self.bump() {
+                Some(')') => break,
+                Some('+') | Some('-') => (),
+                _ => return Err(self.error(at, quoted(&[")", "+", "-"]))),
+            }
+        }
+        Ok((kinetic, mass))
+    }
+
+    /// One braced argument of `\frac`, a product of coefficients.
+    fn fraction_part(&mut self, denominator: bool) -> Result<Coefficient, ParseError> {
+        self.drop_whitespace();
+        let start = self.pos;
+        let range = if self.block("an argument")?.starts_with('{') {
+            start + 1..self.pos - 1
+        } else {
+            start..self.pos
+        };
+        let mut inner = Cursor::new(self.text, range, self.symbols);
+        let mut res = (Rational::ONE, vec![]);
+        while let Some((x, couplings)) = inner.coefficient()? {
+            if denominator {
+                if let Some(&(at, _, _)) = couplings.first() {
+                    return Err(inner.error(at, vec![String::from("a number")]));
+                }
+            }
+            res.0 = res.0 * x;
+            res.1.extend(couplings);
+        }
+        inner.drop_whitespace();
+        if inner.peek().is_some() {
+            return Err(inner.error(inner.pos, vec![String::from("a coefficient")]));
+        }
+        Ok(res)
+    }
+}
+
+/// Terms joined by `+` or `-`, each a product of fields with an optional rational prefactor and
+/// coupling, across any number of lines; `%` starts a comment. A derivative acts on the field
+/// right after it. Terms with two fields are kinetic or mass terms, where the coupling is the mass.
+///
+/// Field declarations may come first, and then replace the default symbols. A term `h.c.` adds
+/// the conjugates of the interactions since the previous one, leaving out Hermitian ones.
+pub fn parse_lagrangian(text: &str) -> Result<(CheckedLagrangian, Symbols), ModelError> {
+    let default = Symbols::default();
+    let mut cursor = Cursor::new(text, 0..text.len(), &default);
+    let symbols = cursor.declarations()?.unwrap_or_default();
+    let mut cursor = Cursor::new(text, cursor.pos..text.len(), &symbols);
+    let mut res = UncheckedLagrangian::new();
+    let mut errors = vec![];
+    let mut sign = Rational::ONE;
+    cursor.drop_whitespace();
+    match cursor.peek() {
+        Some('-') => {
+            cursor.bump();
+            sign = Rational::integer(-1);
+        }
+        Some('+') => {
+            cursor.bump();
+        }
+        _ => (),
+    }
+    // interactions before this are already conjugated
+    let mut conjugated = 0;
+    loop {
+        cursor.drop_whitespace();
+        if cursor.rest().starts_with("h.c.") {
+            if sign != Rational::ONE {
+                let mut error = cursor.error(cursor.pos, vec![String::from("a term")]);
+                error.token = Some(String::from("h.c."));
+                return Err(error.into());
+            }
+            cursor.pos += "h.c.".len();
+            res.push_conjugates(conjugated);
+            conjugated = res.interactions().len();
+            match cursor.separator(vec![])? {
+                Some(x) => sign = x,
+                None => break,
+            }
+            continue;
+        }
+
+        let mut factors = vec![];
+        let mut prefactor = sign;
+        let mut couplings = vec![];
+        // the number of factors before each gamma matrix, with its offset and index
+        let mut gammas = vec![];
+        let mut slashed = false;
+        let mut group = None;
+        // the factor each derivative acts on, with its offset and index
+        let mut derivatives = vec![];
+        let mut indices = vec![];
+        let mut pending = vec![];
+        loop {
+            cursor.drop_whitespace();
+            let at = cursor.pos;
+            if let Some((field, num, index)) = cursor.field()? {
+                if !pending.is_empty() && num != 1 {
+                    let expected = vec![String::from("a field without a power")];
+                    return Err(cursor.error(at, expected).into());
+                }
+                let k = factors.len();
+                derivatives.extend(pending.drain(..).map(|(at, index)| (k, at, index)));
+                if let Some(index) = index {
+                    let index: Rc<str> = Rc::from(index);
+                    indices.extend((k..k + num as usize).map(|k| (k, Rc::clone(&index))));
+                }
+                factors.append(&mut vec![field; num as usize]);
+            } else if let Some(op) = cursor.operator()? {
+                match op {
+                    Operator::Partial(index) => pending.push((at, index)),
+                    Operator::Gamma(index) => gammas.push((factors.len(), at, index)),
+                    Operator::Slashed => {
+                        pending.push((at, None));
+                        gammas.push((factors.len(), at, None));
+                        slashed = true;
+                    }
+                }
+            } else if cursor.peek() == Some('(') {
+                group = Some(cursor.group()?);
+            } else if let Some((x, cs)) = cursor.coefficient()? {
+                // a coupling comes before the fields, so with declarations this is a typo
+                if let Some(&(at, _, _)) = cs.first().filter(|_| symbols.declared) {
+                    if !factors.is_empty() {
+                        return Err(cursor.error(at, symbols.expected()).into());
+                    }
+                }
+                prefactor = prefactor * x;
+                couplings.extend(cs);
+            } else {
+                break;
+            }
+        }
+        if factors.is_empty() {
+            let at = couplings.last().map_or(cursor.pos, |x| x.0);
+            return Err(cursor.error(at, symbols.expected()).into());
+        }
+        if !pending.is_empty() {
+            return Err(cursor.error(cursor.pos, symbols.expected()).into());
+        }
+        if let Some((at, _, _)) = couplings.get(1) {
+            let mut expected = symbols.expected();
+            expected.push(String::from("a number"));
+            return Err(cursor.error(*at, expected).into());
+        }
+
+        if factors.len() > 2 {
+            if let Some((at, _, power)) = couplings.first() {
+                if *power != 1 {
+                    let expected = vec![String::from("a coupling without a power")];
+                    return Err(cursor.error(*at, expected).into());
+                }
+            }
+            if slashed || group.is_some() {
+                errors.push(LagrangianError::OperatorCoupling { term: factors });
+            } else {
+                let mut partials = vec![];
+                for (k, at, index) in derivatives {
+                    let Some(index) = index else {
+                        let at = at + "\\partial".len();
+                        return Err(cursor.error(at, quoted(&["_", "^"])).into());
+                    };
+                    partials.push((k, Rc::from(index)));
+                }
+                let mut matrices = vec![];
+                for (k, at, index) in gammas {
+                    let Some(index) = index else {
+                        let at = at + "\\gamma".len();
+                        return Err(cursor.error(at, quoted(&["_", "^"])).into());
+                    };
+                    matrices.push((k, Rc::from(index)));
+                }
+                let coupling = couplings.first().map(|x| x.1.as_str());
+                let interaction = Interaction::try_new(factors.into_iter()).and_then(|x| {
+                    x.with_coefficient(prefactor, coupling)
+                        .with_derivatives(partials)
+                        .with_gammas(matrices)
+                        .with_indices(indices)
+                });
+                match interaction {
+                    Ok(x) => res.push(x),
+                    Err(e) => errors.push(e),
+                }
+            }
+        } else {
+            let (kinetic, mass) = match (group, derivatives.len(), gammas.len()) {
+                (Some(x), 0, 0) => x,
+                (None, 0, 0) => (None, couplings.first().map(|x| x.1.clone())),
+                (None, 1, 1) => (Some(Kinetic::Dirac), None),
+                (None, 2, 0) => (Some(Kinetic::KleinGordon), None),
+                _ => (None, None),
+            };
+            match Propagator::try_new(factors, kinetic, mass.as_deref()) {
+                Ok(p) => {
+                    if let Err(e) = res.push_propagator(p) {
+                        errors.push(e);
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let mut expected = symbols.expected();
+        expected.push(String::from("a coefficient"));
+        match cursor.separator(expected)? {
+            Some(x) => sign = x,
+            None => break,
+        }
+    }
+    match CheckedLagrangian::try_new(res) {
+        Ok(x) if errors.is_empty() => Ok((x, symbols)),
+        Ok(_) => Err(ModelError::Lagrangian(errors)),
+        Err(mut e) => {
+            errors.append(&mut e);
+            Err(ModelError::Lagrangian(errors))
+        }
+    }
+}
+
+/// Incoming and outgoing particles separated by `->`, or just external legs without it.
+pub fn parse_process(
+    lagrangian: &CheckedLagrangian,
+    symbols: &Symbols,
+    text: &str,
+) -> Result<Vec<Vertex>, ParseError> {
+    let sides = match text.find("->") {
+        Some(i) => vec![(0..i, In), (i + 2..text.len(), Out)],
+        None => vec![(0..text.len(), Unknown)],
+    };
+    let known = || {
+        let names = lagrangian
+            .fields()
+            .iter()
+            .map(|x| x.to_string())
+            .collect_vec();
+        quoted(&names.iter().map(String::as_str).collect_vec())
+    };
+    let mut res = vec![];
+    for (range, inout) in sides {
+        let mut cursor = Cursor::new(text, range, symbols);
+        loop {
+            cursor.drop_whitespace();
+            let start = cursor.pos;
+            let Some((field, num, _)) = cursor.field()? else {
+                break;
+            };
+            let Some(particle) = lagrangian.fields().iter().find(|&x| x == &field) else {
+                let mut error = cursor.error(start, known());
+                error.token = Some(text[start..cursor.pos].trim_end().to_string());
+                return Err(error);
+            };
+            for _ in 0..num {
+                res.push(match inout {
+                    Unknown => Vertex::external(particle.clone()),
+                    In => Vertex::inout(particle.clone(), In),
+                    Out => Vertex::inout(particle.anti(), Out),
+                });
+            }
+        }
+        if cursor.peek().is_some() {
+            let mut expected = known();
+            if inout == In {
+                expected.push(String::from("`->`"));
+            } else {
+                expected.push(String::from("end of input"));
+            }
+            return Err(cursor.error(cursor.pos, expected));
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Offset and token of the error the lagrangian fails to parse with.
+    fn parse_error(text: &str) -> (usize, Option<String>) {
+        match parse_lagrangian(text) {
+            Err(ModelError::Parse(e)) => (e.offset, e.token),
+            _ => panic!("{text} should not parse"),
+        }
+    }
+
+    #[test]
+    fn undeclared_symbol_after_field() {
+        let text = r"field e: spinor; field h: real scalar; \bar e e x + y\bar e e h";
+        assert_eq!(parse_error(text), (48, Some("x".to_string())));
+    }
+
+    #[test]
+    fn caret_under_token() {
+        let text = r"g\phi\bar\psi\psi + \lambda \phi^";
+        let e = parse_lagrangian(text).unwrap_err().render(text);
+        let lines = e.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "unexpected end of input at byte 33, expected a superscript"
+        );
+        assert_eq!(lines[2], format!("{}^", " ".repeat(33)));
+
+        let text = "\\phi^3\n+ g\\chi";
+        let e = parse_lagrangian(text).unwrap_err().render(text);
+        let lines = e.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1..], ["2 | + g\\chi", "       ^^^^"]);
+    }
+
+    #[test]
+    fn process_caret() {
+        let (lagrangian, symbols) = parse_lagrangian(r"g\phi\bar\psi\psi").unwrap();
+        let text = r"\psi \phi -> A";
+        let e = parse_process(&lagrangian, &symbols, text).unwrap_err();
+        assert_eq!((e.offset, e.token.as_deref()), (13, Some("A")));
+    }
+
+    #[test]
+    fn braced_power() {
+        let (braced, _) = parse_lagrangian(r"-\frac{\lambda}{4!}\phi^{4}").unwrap();
+        let (bare, _) = parse_lagrangian(r"-\frac{\lambda}{4!}\phi^4").unwrap();
+        assert_eq!(braced.interactions(), bare.interactions());
+    }
+
+    #[test]
+    fn non_ascii_in_operator() {
+        let text = "\\bar\\psi(i\\gamma^\\mu\\partial_\\mu − m)\\psi";
+        assert_eq!(parse_error(text), (33, Some(String::from("−"))));
+    }
+
+    #[test]
+    fn term_without_field() {
+        let text = r"\phi\bar\psi\psi + \chi";
+        assert_eq!(parse_error(text), (19, Some(r"\chi".to_string())));
+    }
+
+    #[test]
+    fn non_ascii_in_declaration() {
+        let text = "field e： spinor; \\bar e e";
+        assert_eq!(parse_error(text), (7, Some(String::from("："))));
+    }
+
+    #[test]
+    fn suffixed_names() {
+        let text = r"field W^\pm: complex vector; field h: real scalar; g W^{\pm*}_\mu W^\pm_\mu h";
+        let (lagrangian, _) = parse_lagrangian(text).unwrap();
+        let w = Field::new(ComplexVector(false), r"W^\pm");
+        assert!(lagrangian.fields().contains(&w));
+
+        let text = r"field W^+: complex vector; field h: real scalar; g\bar W^+_\mu W_\mu^+ h";
+        assert!(parse_lagrangian(text).is_ok());
+        let text = r"field W^+: complex vector; field h: real scalar; g\bar W^+_\mu W_\mu h";
+        assert_eq!(parse_error(text), (63, Some(String::from("W"))));
+        let text = r"field W^2: complex vector; g\bar W^2_\mu W_\mu^2";
+        assert_eq!(parse_error(text), (8, Some(String::from("2"))));
+    }
+
+    #[test]
+    fn comments_and_lines() {
+        let text = "field e: spinor；\\bar e e";
+        assert_eq!(parse_error(text), (15, Some(String::from("；"))));
+
+        let model = "
+field e: spinor;  % electron
+field h: real scalar;
+
+  \\frac12\\partial_\\mu h\\partial^\\mu h - \\frac12 m^2 h^2
++ \\bar e(i\\gamma^\\mu\\partial_\\mu - m_e)e % - g h^3
+- y\\bar e e h
+";
+        let (lagrangian, _) = parse_lagrangian(model).unwrap();
+        let one_line = [
+            r"field e: spinor; field h: real scalar;",
+            r"\frac12\partial_\mu h\partial^\mu h - \frac12 m^2 h^2",
+            r"+ \bar e(i\gamma^\mu\partial_\mu - m_e)e - y\bar e e h",
+        ]
+        .join(" ");
+        let (one_line, _) = parse_lagrangian(&one_line).unwrap();
+        assert_eq!(lagrangian.interactions(), one_line.interactions());
+        assert_eq!(lagrangian.interactions().len(), 1);
+    }
+}
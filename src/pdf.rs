@@ -0,0 +1,172 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::tikz::Document;
+
+const JOB: &str = "diagrams";
+
+/// Builds so far, so that each one gets its own directory
+static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+pub struct LatexError {
+    pub line: usize,
+    pub diagram: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum PdfError {
+    Io(io::Error),
+    Latex {
+        log: PathBuf,
+        errors: Vec<LatexError>,
+    },
+    Failed {
+        log: PathBuf,
+        status: ExitStatus,
+    },
+}
+
+impl From<io::Error> for PdfError {
+    fn from(e: io::Error) -> Self {
+        PdfError::Io(e)
+    }
+}
+
+impl Display for PdfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PdfError::Io(e) => write!(f, "cannot build the PDF: {}", e),
+            PdfError::Latex { log, errors } => {
+                writeln!(f, "LuaLaTeX reported {} errors:", errors.len())?;
+                for e in errors {
+                    match e.diagram {
+                        Some(k) => write!(f, "\tdiagram {}", k + 1)?,
+                        None => write!(f, "\toutside any diagram")?,
+                    }
+                    writeln!(f, " (line {}): {}", e.line, e.message)?;
+                }
+                write!(f, "see {}", log.display())
+            }
+            PdfError::Failed { log, status } => {
+                write!(f, "LuaLaTeX failed with {}, see {}", status, log.display())
+            }
+        }
+    }
+}
+
+pub fn build(document: &Document, lualatex: &Path, output: &Path) -> Result<(), PdfError> {
+    // LuaLaTeX runs in the build directory, where a relative path would no longer point to it
+    let lualatex = match lualatex.parent() {
+        Some(x) if !x.as_os_str().is_empty() => fs::canonicalize(lualatex)?,
+        _ => lualatex.to_path_buf(),
+    };
+    let dir = std::env::temp_dir().join(format!(
+        "feynman-diagrams-{}-{}",
+        std::process::id(),
+        BUILDS.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(JOB).with_extension("tex"), &document.text)?;
+
+    let status = Command::new(&lualatex)
+        .current_dir(&dir)
+        .args(["-interaction=nonstopmode", "-file-line-error"])
+        .arg(Path::new(JOB).with_extension("tex"))
+        .output()?
+        .status;
+
+    let log = dir.join(JOB).with_extension("log");
+    let errors = errors(document, &fs::read_to_string(&log).unwrap_or_default());
+    if !errors.is_empty() {
+        return Err(PdfError::Latex { log, errors });
+    }
+    if !status.success() {
+        return Err(PdfError::Failed { log, status });
+    }
+
+    fs::copy(dir.join(JOB).with_extension("pdf"), output)?;
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+fn errors(document: &Document, log: &str) -> Vec<LatexError> {
+    let prefix = format!("{}.tex:", JOB);
+    log.lines()
+        .filter_map(|x| {
+            let rest = &x[x.find(&prefix)? + prefix.len()..];
+            let (line, message) = rest.split_once(": ")?;
+            let line = line.parse().ok()?;
+            Some(LatexError {
+                line,
+                diagram: document.diagram_at(line),
+                message: message.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, sync::Mutex};
+
+    use super::*;
+
+    /// Held while a stub is written and run, since running a file another thread still has open
+    /// for writing fails with `ETXTBSY`
+    static STUBS: Mutex<()> = Mutex::new(());
+
+    /// Builds a two-line diagram with a stub LuaLaTeX given by a relative path.
+    fn build_with(name: &str, script: Option<&str>) -> (Result<(), PdfError>, PathBuf) {
+        let _lock = STUBS.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = Path::new("target").join("pdf-stubs");
+        fs::create_dir_all(&dir).unwrap();
+        let stub = dir.join(name);
+        if let Some(script) = script {
+            fs::write(&stub, format!("#!/bin/sh\n{}\n", script)).unwrap();
+            fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let document = Document {
+            text: "\\documentclass{article}\n\\begin{document}\nx\ny\n\\end{document}\n".into(),
+            spans: std::iter::once(3..5).collect(),
+        };
+        let output = dir.join(name).with_extension("pdf");
+        let _ = fs::remove_file(&output);
+        (build(&document, &stub, &output), output)
+    }
+
+    #[test]
+    fn relative_stub_succeeds() {
+        let (result, output) = build_with("ok.sh", Some("echo '%PDF' > diagrams.pdf"));
+        result.unwrap();
+        assert_eq!(fs::read_to_string(output).unwrap(), "%PDF\n");
+    }
+
+    #[test]
+    fn latex_error_is_located() {
+        let script = "echo './diagrams.tex:4: Missing $ inserted.' > diagrams.log; exit 1";
+        match build_with("error.sh", Some(script)).0 {
+            Err(PdfError::Latex { errors, .. }) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].line, 4);
+                assert_eq!(errors[0].diagram, Some(0));
+                assert_eq!(errors[0].message, "Missing $ inserted.");
+            }
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+
+    #[test]
+    fn missing_executable() {
+        match build_with("missing.sh", None).0 {
+            Err(PdfError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+}
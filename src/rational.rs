@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    ops::{Add, Mul},
+};
+
+/// Always kept in lowest terms with a positive denominator.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(try_from = "(i64, i64)", into = "(i64, i64)")]
+pub struct Rational {
+    numerator: i64,
+    denominator: i64,
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Rational {
+    pub const ONE: Rational = Rational {
+        numerator: 1,
+        denominator: 1,
+    };
+
+    pub fn new(numerator: i64, denominator: i64) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let g = gcd(numerator, denominator) * denominator.signum();
+        Some(Rational {
+            numerator: numerator / g,
+            denominator: denominator / g,
+        })
+    }
+
+    pub fn integer(n: i64) -> Self {
+        Rational::new(n, 1).unwrap()
+    }
+
+    /// `None` if it does not fit.
+    pub fn factorial(n: i64) -> Option<Self> {
+        (1..=n)
+            .try_fold(1i64, |acc, k| acc.checked_mul(k))
+            .map(Rational::integer)
+    }
+
+    pub fn recip(self) -> Option<Self> {
+        Rational::new(self.denominator, self.numerator)
+    }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+
+    fn mul(self, rhs: Rational) -> Rational {
+        let a = gcd(self.numerator, rhs.denominator).max(1);
+        let b = gcd(rhs.numerator, self.denominator).max(1);
+        Rational {
+            numerator: (self.numerator / a) * (rhs.numerator / b),
+            denominator: (self.denominator / b) * (rhs.denominator / a),
+        }
+    }
+}
+
+impl Add for Rational {
+    type Output = Rational;
+
+    fn add(self, rhs: Rational) -> Rational {
+        let g = gcd(self.denominator, rhs.denominator);
+        Rational::new(
+            self.numerator * (rhs.denominator / g) + rhs.numerator * (self.denominator / g),
+            self.denominator / g * rhs.denominator,
+        )
+        .unwrap()
+    }
+}
+
+impl TryFrom<(i64, i64)> for Rational {
+    type Error = &'static str;
+
+    fn try_from((numerator, denominator): (i64, i64)) -> Result<Self, Self::Error> {
+        Rational::new(numerator, denominator).ok_or("zero denominator")
+    }
+}
+
+impl From<Rational> for (i64, i64) {
+    fn from(x: Rational) -> Self {
+        (x.numerator, x.denominator)
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
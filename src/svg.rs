@@ -0,0 +1,189 @@
+use itertools::Itertools;
+use std::fmt::Write;
+
+use crate::{diagram::*, field::*, lagrangian::*, layout::*};
+
+const WIDTH: f64 = 240.0;
+const HEIGHT: f64 = 180.0;
+const MARGIN: f64 = 30.0;
+const CAPTION: f64 = 20.0;
+const COLUMNS: usize = 4;
+
+pub fn diagram(d: &Diagram, lagrangian: &CheckedLagrangian) -> String {
+    let mut res = header(WIDTH, HEIGHT);
+    res.push_str(&draw(d, lagrangian, (0.0, 0.0)));
+    res.push_str("</svg>\n");
+    res
+}
+
+/// All diagrams on one sheet, numbered in order; the captions show up as tooltips.
+pub fn sheet<'a>(
+    diagrams: impl IntoIterator<Item = (String, &'a Diagram)>,
+    lagrangian: &CheckedLagrangian,
+) -> String {
+    let diagrams = diagrams.into_iter().collect_vec();
+    let rows = diagrams.len().div_ceil(COLUMNS).max(1);
+    let columns = diagrams.len().clamp(1, COLUMNS);
+    let mut res = header(WIDTH * columns as f64, (HEIGHT + CAPTION) * rows as f64);
+    for (k, (caption, d)) in diagrams.into_iter().enumerate() {
+        let x = WIDTH * (k % COLUMNS) as f64;
+        let y = (HEIGHT + CAPTION) * (k / COLUMNS) as f64;
+        writeln!(res, "<g>\n<title>{}</title>", escape(&caption)).unwrap();
+        res.push_str(&draw(d, lagrangian, (x, y)));
+        writeln!(
+            res,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" font-size=\"12\">#{}</text>\n</g>",
+            x + WIDTH / 2.0,
+            y + HEIGHT + CAPTION / 2.0,
+            k + 1
+        )
+        .unwrap();
+    }
+    res.push_str("</svg>\n");
+    res
+}
+
+fn header(width: f64, height: f64) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\" font-family=\"serif\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n",
+        width, height
+    )
+}
+
+fn draw(d: &Diagram, lagrangian: &CheckedLagrangian, offset: Point) -> String {
+    let Layout { positions, edges } = layout(d);
+    let place = |p: Point| {
+        (
+            offset.0 + MARGIN + p.0 * (WIDTH - 2.0 * MARGIN),
+            offset.1 + MARGIN + p.1 * (HEIGHT - 2.0 * MARGIN),
+        )
+    };
+    let positions = positions.into_iter().map(place).collect_vec();
+
+    let mut res = String::new();
+    for Edge { line, shape } in &edges {
+        let points = sample(*shape, positions[line.from], positions[line.to], 40.0, 96);
+        res.push_str(&propagator(line, lagrangian.mass(&line.field), &points));
+    }
+    for (u, &(x, y)) in d.nodes().iter().zip(&positions) {
+        if let Some(p) = u.particle() {
+            let anchor = if x < offset.0 + WIDTH / 2.0 {
+                ("end", x - 6.0)
+            } else {
+                ("start", x + 6.0)
+            };
+            writeln!(
+                res,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\" font-size=\"14\">{}</text>",
+                anchor.1,
+                y + 5.0,
+                anchor.0,
+                escape(&label(&p))
+            )
+            .unwrap();
+        } else {
+            writeln!(res, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\"/>", x, y).unwrap();
+        }
+    }
+    res
+}
+
+/// Massive lines are drawn thicker, with the mass next to the middle of the line.
+fn propagator(line: &Line, mass: Option<&str>, points: &[Point]) -> String {
+    let mut res = String::new();
+    let (path, dash) = match line.field.kind() {
+        RealScalar | ComplexScalar(_) => (points.to_vec(), " stroke-dasharray=\"6,4\""),
+        RealVector | ComplexVector(_) => (wave(points, 3.0, 8.0), ""),
+        Spinor(_) => (points.to_vec(), ""),
+    };
+    let width = if mass.is_some() {
+        " stroke-width=\"2\""
+    } else {
+        ""
+    };
+    writeln!(
+        res,
+        "<polyline fill=\"none\" stroke=\"black\"{}{} points=\"{}\"/>",
+        width,
+        dash,
+        path.iter()
+            .map(|p| format!("{:.1},{:.1}", p.0, p.1))
+            .join(" ")
+    )
+    .unwrap();
+
+    if let Some(forward) = line.arrow() {
+        let mid = points.len() / 2;
+        let (a, b) = (points[mid - 1], points[mid + 1]);
+        let (dx, dy) = if forward {
+            (b.0 - a.0, b.1 - a.1)
+        } else {
+            (a.0 - b.0, a.1 - b.1)
+        };
+        let len = (dx * dx + dy * dy).sqrt().max(1e-9);
+        let (ux, uy) = (dx / len, dy / len);
+        let (x, y) = points[mid];
+        let tip = (x + 5.0 * ux, y + 5.0 * uy);
+        let left = (x - 4.0 * ux - 4.0 * uy, y - 4.0 * uy + 4.0 * ux);
+        let right = (x - 4.0 * ux + 4.0 * uy, y - 4.0 * uy - 4.0 * ux);
+        writeln!(
+            res,
+            "<polygon points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\"/>",
+            tip.0, tip.1, left.0, left.1, right.0, right.1
+        )
+        .unwrap();
+    }
+
+    if let Some(mass) = mass {
+        let mid = points.len() / 2;
+        let (a, b) = (points[mid - 1], points[mid + 1]);
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let len = (dx * dx + dy * dy).sqrt().max(1e-9);
+        // always on the upper side of the line, whichever way it runs
+        let (nx, ny) = if dx >= 0.0 {
+            (dy / len, -dx / len)
+        } else {
+            (-dy / len, dx / len)
+        };
+        let (x, y) = points[mid];
+        writeln!(
+            res,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" font-size=\"11\" \
+             font-style=\"italic\">{}</text>",
+            x + 12.0 * nx,
+            y + 12.0 * ny + 4.0,
+            escape(&plain(mass))
+        )
+        .unwrap();
+    }
+    res
+}
+
+/// Offsets a polyline sideways by a sine wave of the given amplitude and wavelength.
+fn wave(points: &[Point], amplitude: f64, wavelength: f64) -> Vec<Point> {
+    let mut s = 0.0;
+    let mut res = vec![];
+    for (k, &(x, y)) in points.iter().enumerate() {
+        if k > 0 {
+            let (px, py) = points[k - 1];
+            s += ((x - px).powi(2) + (y - py).powi(2)).sqrt();
+        }
+        let (a, b) = (
+            points[k.saturating_sub(1)],
+            points[(k + 1).min(points.len() - 1)],
+        );
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let len = (dx * dx + dy * dy).sqrt().max(1e-9);
+        let off = amplitude * (2.0 * std::f64::consts::PI * s / wavelength).sin();
+        res.push((x - dy / len * off, y + dx / len * off));
+    }
+    res
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
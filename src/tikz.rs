@@ -0,0 +1,139 @@
+use itertools::Itertools;
+use std::{collections::HashMap, fmt::Write, ops::Range};
+
+use crate::{diagram::*, field::*, lagrangian::*};
+
+const PREAMBLE: &str = r"% !TeX program = lualatex
+\documentclass{article}
+\usepackage[compat=1.1.0]{tikz-feynman}
+\begin{document}
+";
+
+const POSTAMBLE: &str = r"\end{document}
+";
+
+pub fn style(kind: FieldKind) -> &'static str {
+    match kind {
+        RealScalar => "scalar",
+        ComplexScalar(true) => "charged scalar",
+        ComplexScalar(false) => "anti charged scalar",
+        RealVector => "boson",
+        ComplexVector(true) => "charged boson",
+        ComplexVector(false) => "anti charged boson",
+        Spinor(true) => "anti fermion",
+        Spinor(false) => "fermion",
+    }
+}
+
+pub struct Document {
+    pub text: String,
+    /// Lines (1-based, end exclusive) taken by each diagram
+    pub spans: Vec<Range<usize>>,
+}
+
+impl Document {
+    pub fn diagram_at(&self, line: usize) -> Option<usize> {
+        self.spans.iter().position(|x| x.contains(&line))
+    }
+}
+
+pub fn document<'a>(
+    diagrams: impl IntoIterator<Item = (String, &'a Diagram)>,
+    lagrangian: &CheckedLagrangian,
+) -> Document {
+    let mut text = String::from(PREAMBLE);
+    let mut spans = vec![];
+    let mut line = PREAMBLE.lines().count() + 1;
+    for (caption, d) in diagrams {
+        let mut chunk = String::new();
+        writeln!(chunk, "\\noindent {}\\\\", math_caption(&caption)).unwrap();
+        chunk.push_str(&diagram(d, lagrangian));
+        writeln!(chunk, "\\bigskip").unwrap();
+        let len = chunk.lines().count();
+        writeln!(text).unwrap();
+        text.push_str(&chunk);
+        spans.push(line + 1..line + 1 + len);
+        line += 1 + len;
+    }
+    writeln!(text).unwrap();
+    text.push_str(POSTAMBLE);
+    Document { text, spans }
+}
+
+/// Puts the coupling order ending a caption, like `coupling order \lambda^2 g`, in math mode.
+fn math_caption(caption: &str) -> String {
+    const ORDER: &str = "coupling order ";
+    let Some(at) = caption.find(ORDER).map(|x| x + ORDER.len()) else {
+        return caption.to_string();
+    };
+    let order = caption[at..].split(' ').map(|x| match x.split_once('^') {
+        Some((g, n)) => format!("{}^{{{}}}", g, n),
+        None => x.to_string(),
+    });
+    format!("{}\\({}\\)", &caption[..at], order.format(" "))
+}
+
+/// Massive lines are labelled with their mass.
+pub fn diagram(d: &Diagram, lagrangian: &CheckedLagrangian) -> String {
+    let nodes = d.nodes();
+    let first = |inout| {
+        nodes
+            .iter()
+            .position(|u| matches!((u.direction(), inout), (Some(In), In) | (Some(Out), Out)))
+    };
+    let mut res = String::from("\\feynmandiagram [layered layout");
+    if let (Some(a), Some(b)) = (first(In), first(Out)) {
+        write!(res, ", horizontal=v{} to v{}", a, b).unwrap();
+    }
+    res.push_str("] {\n");
+
+    for (i, u) in nodes.iter().enumerate() {
+        if let Some(p) = u.particle() {
+            writeln!(res, "\tv{} [particle=\\({}\\)],", i, p).unwrap();
+        }
+    }
+
+    let lines = d.lines();
+    let parallel = lines.iter().map(|x| (x.from, x.to)).counts();
+    let mut seen: HashMap<_, usize> = parallel.keys().map(|&k| (k, 0)).collect();
+    for line in &lines {
+        let k = seen.get_mut(&(line.from, line.to)).unwrap();
+        let m = parallel[&(line.from, line.to)];
+        let mut text = style(line.field.kind()).to_string();
+        if let Some(mass) = lagrangian.mass(&line.field) {
+            text += &format!(", edge label'=\\({}\\)", mass);
+        }
+        if line.from == line.to {
+            let angle = 90 * *k;
+            text += &format!(
+                ", out={}, in={}, loop, min distance=2cm",
+                angle + 135,
+                angle + 45
+            );
+        } else if m > 1 {
+            let bend = 40 * (2 * *k as isize - (m as isize - 1)) / (m as isize - 1);
+            if bend != 0 {
+                text += &format!(", bend left={}", bend);
+            }
+        }
+        *k += 1;
+        writeln!(res, "\tv{} -- [{}] v{},", line.from, text, line.to).unwrap();
+    }
+    res.push_str("};\n");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coupling_order_is_math() {
+        let caption = r"Order 2, 1 loops, sign +1, coupling order \lambda^2 g";
+        assert_eq!(
+            math_caption(caption),
+            r"Order 2, 1 loops, sign +1, coupling order \(\lambda^{2} g\)"
+        );
+        assert_eq!(math_caption("Order 0, 0 loops"), "Order 0, 0 loops");
+    }
+}